@@ -1,32 +1,176 @@
-use std::collections::{HashSet, HashMap};
+use std::{collections::{HashSet, HashMap}, fmt};
 
 use priority_queue::PriorityQueue;
 
-use crate::{position::{Position, self}, rotation::Rotation};
+use crate::{bounds::Bounds, occupancy::{Occupancy, CHUNK_SIZE}, position::Position, rotation::Rotation};
 
 
-pub struct Astar {
+// Furthest a pipe-to-ground exit can be from its entrance.
+pub const PIPE_TUNNEL_REACH: isize = 10;
+// Nodes a search may expand before it gives up.
+pub const DEFAULT_BUDGET: usize = 100_000;
+
+// Tiles a path may use at all, the keep-out set is left to the evade tiles.
+#[derive(Clone, Debug)]
+pub enum Region {
+    Everywhere,
+    Rect(Bounds),
+    Mask(Occupancy),
+}
+
+impl Region {
+    // The smallest box of whole chunks around `bounds`.
+    pub fn chunks(bounds: Bounds) -> Self {
+        Region::Rect(Bounds {
+            min_x: bounds.min_x.div_euclid(CHUNK_SIZE) * CHUNK_SIZE,
+            max_x: (bounds.max_x + CHUNK_SIZE - 1).div_euclid(CHUNK_SIZE) * CHUNK_SIZE,
+            min_y: bounds.min_y.div_euclid(CHUNK_SIZE) * CHUNK_SIZE,
+            max_y: (bounds.max_y + CHUNK_SIZE - 1).div_euclid(CHUNK_SIZE) * CHUNK_SIZE,
+        })
+    }
+
+    pub fn move_rel(&self, x: isize, y: isize) -> Self {
+        match self {
+            Region::Everywhere => Region::Everywhere,
+            Region::Rect(b) => Region::Rect(b.move_rel(x, y)),
+            Region::Mask(tiles) => Region::Mask(tiles.move_rel(x, y)),
+        }
+    }
+
+    pub fn contains(&self, position: Position) -> bool {
+        match self {
+            Region::Everywhere => true,
+            Region::Rect(bounds) => bounds.contains(position.x, position.y),
+            Region::Mask(tiles) => tiles.contains(position.x, position.y),
+        }
+    }
+
+    pub fn contains_rect(&self, bounds: Bounds) -> bool {
+        match self {
+            Region::Everywhere => true,
+            Region::Rect(region) => region.contains_rect(bounds),
+            Region::Mask(tiles) => bounds.tiles().all(|(x, y)| tiles.contains(x, y)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NoPath {
+    // Every node that can be reached from the start within the region was expanded.
+    Unreachable,
+    // The search ran out of node expansions.
+    OutOfBudget(usize),
+}
+
+impl fmt::Display for NoPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NoPath::Unreachable => write!(f, "the end can not be reached within the routing region"),
+            NoPath::OutOfBudget(budget) => write!(f, "no path found within {} node expansions", budget),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Costs {
+    // Cost of every tile a path covers, undergrounds included.
+    pub tile: isize,
+    // Extra cost of a belt that does not face the same way as the one before it.
+    pub turn: isize,
+    // Extra cost of a tile next to a blocked one, which keeps paths out of the space around other buildings.
+    pub near_reserved: isize,
+    // Extra cost of an underground pair on top of the tiles it covers, so a short detour over ground still wins.
+    pub underground: isize,
+}
+
+impl Default for Costs {
+    fn default() -> Self {
+        Costs { tile: 1, turn: 1, near_reserved: 1, underground: 4 }
+    }
+}
+
+pub struct Astar<'a> {
     begin: Position,
     end: Position,
-    evade: HashSet<Position>,
+    // Tiles taken by buildings, shared by every search over the same layout.
+    blocked: &'a Occupancy,
+    // Tiles only this search keeps out of.
+    evade: Occupancy,
+    // Furthest an underground exit can be from its entrance, 0 disables undergrounds.
+    underground_reach: isize,
+    costs: Costs,
+    region: Region,
+    budget: usize,
+    // Tiles that open belt ends carrying other items run into. A belt there would get those items side-loaded onto one of its lanes.
+    spill: Occupancy,
+    // Extra cost of putting a belt on a tile, the router uses it to make tiles that are wanted by several nets expensive.
+    penalties: HashMap<Position, isize>,
 }
 
-impl Astar {
-    pub fn new(begin: Position, end: Position, evade: HashSet<Position>) -> Self {
-        Astar { begin, end, evade}
+impl<'a> Astar<'a> {
+    pub fn new(begin: Position, end: Position, blocked: &'a Occupancy) -> Self {
+        Astar { begin, end, blocked, evade: Occupancy::new(), underground_reach: 0, costs: Costs::default(), region: Region::Everywhere, budget: DEFAULT_BUDGET, spill: Occupancy::new(), penalties: HashMap::new() }
+    }
+
+    pub fn set_underground(&mut self, reach: isize) {
+        self.underground_reach = reach;
+    }
+
+    pub fn set_costs(&mut self, costs: Costs) {
+        self.costs = costs;
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+    }
+
+    pub fn set_budget(&mut self, budget: usize) {
+        self.budget = budget;
+    }
+
+    pub fn set_evade(&mut self, evade: Occupancy) {
+        self.evade = evade;
+    }
+
+    pub fn set_spill(&mut self, spill: Occupancy) {
+        self.spill = spill;
+    }
+
+    pub fn set_penalties(&mut self, penalties: HashMap<Position, isize>) {
+        self.penalties = penalties;
+    }
+
+    fn penalty(&self, position: Position) -> isize {
+        self.penalties.get(&Position::north(position.x, position.y)).copied().unwrap_or(0)
+    }
+
+    fn near_reserved(&self, position: Position) -> bool {
+        Rotation::CARDINAL.into_iter().any(|rotation| !self.is_clear(Position { rotation, ..position }.forward()))
+    }
+
+    // Cost of putting a belt on `to` coming from `from`, on top of the tiles it covers.
+    fn extra_cost(&self, from: Position, to: Position) -> isize {
+        let mut cost = self.penalty(to);
+        if from.rotation != to.rotation {
+            cost += self.costs.turn;
+        }
+        if self.costs.near_reserved != 0 && self.near_reserved(to) {
+            cost += self.costs.near_reserved;
+        }
+        cost
     }
 
     pub fn construct_path(&self, node: Position, parents: &HashMap<Position, Position>) -> Vec<Position> {
         let mut path = vec![node];
         let mut current_node = node;
         while let Some(parent) = parents.get(&current_node) {
-            current_node = parent.clone();
+            current_node = *parent;
             path.push(current_node);
         }
         path
     }
 
-    pub fn astar_belt(&self) -> Option<Vec<Position>> {
+    pub fn astar_belt(&self) -> Result<Vec<Position>, NoPath> {
         let mut open_list = PriorityQueue::new();
         let mut closed_list = HashSet::new();
         let mut parents = HashMap::new();
@@ -34,35 +178,48 @@ impl Astar {
         gs.insert(self.begin, 0);
 
         for successor in self.belt_successors(self.begin) {
-            gs.insert(successor, 0);
+            if successor != self.end && !self.is_free(successor) {
+                continue;
+            }
+            gs.insert(successor, self.extra_cost(self.begin, successor));
             open_list.push(successor, -self.f(successor, &gs));
         }
-
-        while let Some((node, f)) = open_list.pop() {
-            let successor_cost = gs.get(&node).unwrap() + 1;
-            if successor_cost > 500 {
-                return None;
+        for (successor, cost) in self.underground_successors(self.begin) {
+            let cost = cost + self.penalty(self.begin.forward()) + self.extra_cost(self.begin, successor);
+            if self.is_free(successor) && gs.get(&successor).map(|g| *g > cost).unwrap_or(true) {
+                gs.insert(successor, cost);
+                open_list.push(successor, -self.f(successor, &gs));
             }
+        }
 
-            for successor in self.belt_successors(node).drain(0..) {
-                if successor == self.end {
-                    parents.insert(successor, node);
-                    return Some(self.construct_path(successor, &parents));
-                }
+        // The tile after the start holds the first belt, a path turning back can neither cross it nor tunnel from it.
+        let first = self.begin.forward();
+        let is_first = |p: Position| (p.x, p.y) == (first.x, first.y);
 
-                if successor.y < 0 {
-                    continue;
-                }
+        let mut expanded = 0;
+        while let Some((node, _)) = open_list.pop() {
+            // The end is only done once it is the cheapest open node, so the penalties of the ways into it count.
+            if node == self.end {
+                return Ok(self.construct_path(node, &parents));
+            }
+            let g = *gs.get(&node).unwrap();
+            expanded += 1;
+            if expanded > self.budget {
+                return Err(NoPath::OutOfBudget(self.budget));
+            }
 
-                let mut successor_north = successor; // Check if we can even add nodes here.
-                successor_north.rotation = Rotation::North;
-                if self.evade.contains(&successor_north) {
+            let mut successors: Vec<(Position, isize)> = self.belt_successors(node).into_iter().map(|s| (s, g + self.costs.tile + self.extra_cost(node, s))).collect();
+            if !is_first(node.forward()) {
+                successors.extend(self.underground_successors(node).into_iter().map(|(s, cost)| (s, g + cost + self.penalty(node.forward()) + self.extra_cost(node, s))));
+            }
+            for (successor, successor_cost) in successors {
+                if successor != self.end && (!self.is_free(successor) || is_first(successor)) {
                     continue;
                 }
 
                 // Check if there already is a fast path from successor to here
                 if let Some(g) = gs.get(&successor) {
-                    if *g < successor_cost {
+                    if *g <= successor_cost {
                         continue;
                     }
                 }
@@ -76,7 +233,7 @@ impl Astar {
             closed_list.insert(node);
         }
 
-        None
+        Err(NoPath::Unreachable)
     }
 
     pub fn f(&self, node: Position, gs: &HashMap<Position, isize>) -> isize {
@@ -86,15 +243,123 @@ impl Astar {
         }
     }
 
+    // Every tile costs at least `tile` and a belt that has to move along both axes turns at least once, so this never overestimates.
     pub fn h(&self, node: Position) -> isize {
-        return (node.x - self.end.x).abs() + (node.y - self.end.y).abs()
+        let turn = if node.x != self.end.x && node.y != self.end.y { self.costs.turn } else { 0 };
+        self.distance(node) + turn
+    }
+
+    // Pipes do not pay for turns.
+    fn distance(&self, node: Position) -> isize {
+        ((node.x - self.end.x).abs() + (node.y - self.end.y).abs()) * self.costs.tile
+    }
+
+    fn is_free(&self, position: Position) -> bool {
+        self.region.contains(position) && self.is_clear(position) && !self.spill.contains(position.x, position.y)
+    }
+
+    fn is_clear(&self, position: Position) -> bool {
+        !self.blocked.contains(position.x, position.y) && !self.evade.contains(position.x, position.y)
+    }
+
+    // Pipes connect to all their neighbours, so a node only has a direction right after a pipe-to-ground exit,
+    // which connects straight ahead only. Nodes are marked with whether they are such an exit.
+    // The path runs from the end back to the tile after the start, an exit's entrance is the tile after its predecessor.
+    pub fn astar_pipe(&self) -> Result<Vec<(Position, bool)>, NoPath> {
+        let mut open_list = PriorityQueue::new();
+        let mut parents = HashMap::new();
+        let mut gs = HashMap::new();
+        let start = (Position::north(self.begin.x, self.begin.y), false);
+        let end = Position::north(self.end.x, self.end.y);
+        gs.insert(start, 0);
+        open_list.push(start, -self.distance(start.0));
+
+        let mut expanded = 0;
+        while let Some((node, _)) = open_list.pop() {
+            let g = *gs.get(&node).unwrap();
+            expanded += 1;
+            if expanded > self.budget {
+                return Err(NoPath::OutOfBudget(self.budget));
+            }
+
+            for (successor, cost) in self.pipe_successors(node, end) {
+                if successor == (end, false) {
+                    parents.insert(successor, node);
+                    let mut path = vec![successor];
+                    let mut current = node;
+                    while let Some(parent) = parents.get(&current) {
+                        path.push(current);
+                        current = *parent;
+                    }
+                    return Ok(path);
+                }
+
+                let successor_cost = g + cost;
+                if gs.get(&successor).map(|g| *g <= successor_cost).unwrap_or(false) {
+                    continue;
+                }
+                gs.insert(successor, successor_cost);
+                parents.insert(successor, node);
+                open_list.push(successor, -(successor_cost + self.distance(successor.0)));
+            }
+        }
+
+        Err(NoPath::Unreachable)
+    }
+
+    fn pipe_successors(&self, (node, exit): (Position, bool), end: Position) -> Vec<((Position, bool), isize)> {
+        let directions = if exit { vec![node.rotation] } else { Rotation::CARDINAL.to_vec() };
+
+        let mut successors = Vec::new();
+        for direction in directions {
+            let next = Position::new(node.x, node.y, direction).forward();
+            let pipe = Position::north(next.x, next.y);
+            if pipe == end {
+                successors.push(((pipe, false), self.costs.tile));
+                continue;
+            }
+            if !self.region.contains(next) || !self.is_clear(next) {
+                continue;
+            }
+            successors.push(((pipe, false), self.costs.tile));
+
+            // The entrance goes on the next tile, the exit somewhere further in the same direction.
+            let mut tunnel_exit = next;
+            for distance in 1..=self.underground_reach {
+                tunnel_exit = tunnel_exit.forward();
+                if distance > 1 && self.region.contains(tunnel_exit) && self.is_clear(tunnel_exit) && Position::north(tunnel_exit.x, tunnel_exit.y) != end {
+                    successors.push(((tunnel_exit, true), (distance + 1) * self.costs.tile + self.costs.underground));
+                }
+            }
+        }
+        successors
+    }
+
+    // Exits of an underground pair whose entrance goes on the tile in front of this node, with the cost of the jump.
+    // The path only holds the exit, the entrance is always the tile right after its predecessor.
+    pub fn underground_successors(&self, node: Position) -> Vec<(Position, isize)> {
+        let entrance = node.forward();
+        if self.underground_reach == 0 || !self.is_free(entrance) || entrance == self.end {
+            return Vec::new();
+        }
+
+        let mut successors = Vec::new();
+        let mut exit = entrance;
+        for distance in 1..=self.underground_reach {
+            exit = exit.forward();
+            // Next to each other they are just two belts.
+            if distance > 1 && (exit == self.end || self.is_free(exit)) {
+                successors.push((exit, (distance + 1) * self.costs.tile + self.costs.underground));
+            }
+        }
+        successors
     }
 
     pub fn belt_successors(&self, node: Position) -> Vec<Position> {
         vec![
             node.forward(),
-            node.forward().rotate_left(),
-            node.forward().rotate_right(),
+            node.forward().turned(Rotation::West),
+            node.forward().turned(Rotation::East),
         ]
     }
 }
\ No newline at end of file
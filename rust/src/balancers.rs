@@ -0,0 +1,129 @@
+use crate::{belts::BeltTier, entity::Entity, grid::Grid, position::Position, recipes::Recipe, rotation::Rotation};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Part {
+    Belt,
+    Splitter,
+    UndergroundIn,
+    UndergroundOut,
+}
+
+// Belts flow north through a balancer. The output belts are the top row, the input belts end right below the bottom row.
+#[derive(Debug, PartialEq)]
+pub struct Balancer {
+    pub name: &'static str,
+    pub height: isize,
+    // Columns of the input and output belts.
+    pub inputs: &'static [isize],
+    pub outputs: &'static [isize],
+    // Splitters are given by their position, the right one of their two tiles.
+    pub parts: &'static [(Part, isize, isize, Rotation)],
+}
+
+use Part::*;
+use Rotation::*;
+
+pub const BALANCERS: &[Balancer] = &[
+    Balancer {
+        name: "1-2",
+        height: 2,
+        inputs: &[0],
+        outputs: &[0, 1],
+        parts: &[(Splitter, 1, 1, North), (Belt, 0, 0, North), (Belt, 1, 0, North)],
+    },
+    // A splitter with one output blocked sends everything out of the other one.
+    Balancer {
+        name: "2-1",
+        height: 2,
+        inputs: &[0, 1],
+        outputs: &[0],
+        parts: &[(Splitter, 1, 1, North), (Belt, 0, 0, North)],
+    },
+    Balancer {
+        name: "2-2",
+        height: 2,
+        inputs: &[0, 1],
+        outputs: &[0, 1],
+        parts: &[(Splitter, 1, 1, North), (Belt, 0, 0, North), (Belt, 1, 0, North)],
+    },
+    Balancer {
+        name: "1-4",
+        height: 4,
+        inputs: &[1],
+        outputs: &[0, 1, 2, 3],
+        parts: &[
+            (Splitter, 2, 3, North),
+            (Belt, 1, 2, West), (Belt, 0, 2, North), (Belt, 2, 2, East), (Belt, 3, 2, North),
+            (Splitter, 1, 1, North), (Splitter, 3, 1, North),
+            (Belt, 0, 0, North), (Belt, 1, 0, North), (Belt, 2, 0, North), (Belt, 3, 0, North),
+        ],
+    },
+    Balancer {
+        name: "4-1",
+        height: 4,
+        inputs: &[0, 1, 2, 3],
+        outputs: &[1],
+        parts: &[
+            (Splitter, 1, 3, North), (Splitter, 3, 3, North),
+            (Belt, 1, 2, North), (Belt, 2, 2, North),
+            (Splitter, 2, 1, North),
+            (Belt, 1, 0, North),
+        ],
+    },
+    // Both halves are split, then one belt of each half crosses over the other through an underground
+    // so the last two splitters each get a belt from either half.
+    Balancer {
+        name: "4-4",
+        height: 6,
+        inputs: &[1, 2, 3, 4],
+        outputs: &[0, 1, 2, 3],
+        parts: &[
+            (Splitter, 2, 5, North), (Splitter, 4, 5, North),
+            (Belt, 1, 4, West), (Belt, 0, 4, North), (UndergroundIn, 2, 4, North), (Belt, 3, 4, North), (Belt, 4, 4, North),
+            (Belt, 0, 3, North), (Belt, 1, 3, North), (Belt, 2, 3, West), (Belt, 3, 3, West), (Belt, 4, 3, North),
+            (Belt, 0, 2, North), (Belt, 1, 2, North), (UndergroundOut, 2, 2, North), (Belt, 3, 2, North), (Belt, 4, 2, West),
+            (Splitter, 1, 1, North), (Splitter, 3, 1, North),
+            (Belt, 0, 0, North), (Belt, 1, 0, North), (Belt, 2, 0, North), (Belt, 3, 0, North),
+        ],
+    },
+];
+
+pub fn balancer(name: &str) -> Option<&'static Balancer> {
+    BALANCERS.iter().find(|b| b.name == name)
+}
+
+// Smallest balancer that merges `belts` belts into one.
+pub fn merger(belts: usize) -> Option<&'static Balancer> {
+    BALANCERS.iter().filter(|b| b.outputs.len() == 1 && b.inputs.len() >= belts).min_by_key(|b| b.inputs.len())
+}
+
+// Most belts a single balancer merges into one.
+pub fn most_merged() -> usize {
+    BALANCERS.iter().filter(|b| b.outputs.len() == 1).map(|b| b.inputs.len()).max().unwrap_or(1)
+}
+
+// A grid holding only the balancer, carrying the product of `recipe`. Its inputs are the input belts from left to right,
+// the leftmost output belt is the output and the others are extra outputs.
+pub fn build(balancer: &Balancer, recipe: Recipe, belt: &'static BeltTier) -> Grid {
+    let mut grid = Grid::new(recipe);
+    grid.set_belt(belt);
+    for (part, x, y, rotation) in balancer.parts {
+        let entity = match part {
+            Belt => Entity::belt(belt),
+            Splitter => Entity::splitter(belt),
+            UndergroundIn => Entity::underground(belt, true),
+            UndergroundOut => Entity::underground(belt, false),
+        };
+        grid.place_entity(entity, *y, *x, *rotation, 1, 0);
+    }
+
+    for x in balancer.inputs {
+        grid.add_input(Position { x: *x, y: balancer.height, rotation: North });
+    }
+    let mut outputs = balancer.outputs.iter().map(|x| Position { x: *x, y: 0, rotation: North });
+    grid.set_output(outputs.next().unwrap());
+    for output in outputs {
+        grid.add_extra_output(output);
+    }
+    grid
+}
@@ -0,0 +1,92 @@
+use crate::{position::Position, rotation::Rotation};
+
+#[derive(Debug, PartialEq)]
+pub struct BeltTier {
+    pub name: &'static str,
+    // Items per second on one of the two lanes.
+    pub lane_throughput: f64,
+    pub underground_name: &'static str,
+    // Furthest an underground exit can be from its entrance.
+    pub underground_reach: isize,
+    pub splitter_name: &'static str,
+}
+
+impl BeltTier {
+    pub fn throughput(&self) -> f64 {
+        2.0 * self.lane_throughput
+    }
+}
+
+// Ordered from cheapest to most expensive.
+pub const BELT_TIERS: &[BeltTier] = &[
+    BeltTier { name: "transport-belt", lane_throughput: 7.5, underground_name: "underground-belt", underground_reach: 5, splitter_name: "splitter" },
+    BeltTier { name: "fast-transport-belt", lane_throughput: 15.0, underground_name: "fast-underground-belt", underground_reach: 7, splitter_name: "fast-splitter" },
+    BeltTier { name: "express-transport-belt", lane_throughput: 22.5, underground_name: "express-underground-belt", underground_reach: 9, splitter_name: "express-splitter" },
+];
+
+pub fn belt_tier(name: &str) -> Option<&'static BeltTier> {
+    BELT_TIERS.iter().find(|b| b.name == name)
+}
+
+pub fn fastest() -> &'static BeltTier {
+    &BELT_TIERS[BELT_TIERS.len() - 1]
+}
+
+// Cheapest tier up to `max` with a lane that can carry `amount_per_sec`.
+pub fn cheapest_lane(amount_per_sec: f64, max: &'static BeltTier) -> Option<&'static BeltTier> {
+    BELT_TIERS.iter().take_while(|b| b.lane_throughput <= max.lane_throughput).find(|b| b.lane_throughput >= amount_per_sec - 1e-9)
+}
+
+// Cheapest tier up to `max` that can carry `amount_per_sec` over both lanes.
+pub fn cheapest_belt(amount_per_sec: f64, max: &'static BeltTier) -> Option<&'static BeltTier> {
+    BELT_TIERS.iter().take_while(|b| b.lane_throughput <= max.lane_throughput).find(|b| b.throughput() >= amount_per_sec - 1e-9)
+}
+
+// Lanes as seen when looking in the direction the belt moves.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Lane {
+    Left,
+    Right,
+}
+
+impl Lane {
+    pub fn index(&self) -> usize {
+        match self {
+            Lane::Left => 0,
+            Lane::Right => 1,
+        }
+    }
+
+    // Lane a belt moving towards `feeder` puts everything on when it runs into the side of a belt moving towards `belt`.
+    pub fn side_loaded(belt: Rotation, feeder: Rotation) -> Option<Lane> {
+        if feeder == belt.right() {
+            Some(Lane::Left)
+        } else if feeder == belt.left() {
+            Some(Lane::Right)
+        } else {
+            None
+        }
+    }
+
+    // Inserters drop on the far lane, which lies in the direction they drop in.
+    pub fn dropped(belt: Rotation, drop: Rotation) -> Option<Lane> {
+        if drop == belt.left() {
+            Some(Lane::Left)
+        } else if drop == belt.right() {
+            Some(Lane::Right)
+        } else {
+            None
+        }
+    }
+}
+
+// The belt that side-loads onto `lane` of `belt`, which makes `belt` the stem of a T-junction.
+// It sits on the side of that lane and faces the belt.
+pub fn feeder(belt: Position, lane: Lane) -> Position {
+    let side = match lane {
+        Lane::Left => belt.rotation.left(),
+        Lane::Right => belt.rotation.right(),
+    };
+    let tile = Position { rotation: side, ..belt }.forward();
+    Position { rotation: side.left().left(), ..tile }
+}
@@ -1,18 +1,19 @@
 use std::cmp::{min, max};
 
+use crate::rotation::Rotation;
 
-
-
-
-#[derive(Copy, Clone, Debug)]
+// A rectangle of tiles, the max edges are exclusive. Rectangles without tiles are empty, the operations here
+// return `Bounds::new()` for them so that all empty rectangles compare equal.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
 pub struct Bounds {
     pub min_x: isize,
     pub max_x: isize,
-    pub min_y: isize, 
+    pub min_y: isize,
     pub max_y: isize,
 }
 
 impl Bounds {
+    // The empty rectangle.
     pub fn new() -> Self {
         Bounds {
             min_x: 0,
@@ -22,33 +23,129 @@ impl Bounds {
         }
     }
 
-    pub fn combine(&self, other: Bounds) -> Self {
+    pub fn with_size(x: isize, y: isize, w: isize, h: isize) -> Self {
+        Bounds { min_x: x, max_x: x + w, min_y: y, max_y: y + h }.normalized()
+    }
+
+    fn normalized(self) -> Self {
+        if self.is_empty() { Bounds::new() } else { self }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min_x >= self.max_x || self.min_y >= self.max_y
+    }
+
+    pub fn width(&self) -> isize {
+        if self.is_empty() { 0 } else { self.max_x - self.min_x }
+    }
+
+    pub fn height(&self) -> isize {
+        if self.is_empty() { 0 } else { self.max_y - self.min_y }
+    }
+
+    pub fn area(&self) -> isize {
+        self.width() * self.height()
+    }
+
+    // The smallest rectangle around both, empty ones add nothing.
+    pub fn union(&self, other: Bounds) -> Self {
+        if self.is_empty() {
+            return other.normalized();
+        }
+        if other.is_empty() {
+            return *self;
+        }
         Bounds {
             min_x: min(self.min_x, other.min_x),
             max_x: max(self.max_x, other.max_x),
             min_y: min(self.min_y, other.min_y),
-            max_y: max(self.max_y, other.max_y)
+            max_y: max(self.max_y, other.max_y),
         }
     }
 
-    pub fn area(&self) -> isize {
-        (self.max_x - self.min_x) * (self.max_y - self.min_y)
+    pub fn intersection(&self, other: Bounds) -> Self {
+        Bounds {
+            min_x: max(self.min_x, other.min_x),
+            max_x: min(self.max_x, other.max_x),
+            min_y: max(self.min_y, other.min_y),
+            max_y: min(self.max_y, other.max_y),
+        }.normalized()
+    }
+
+    // Whether the two share a tile.
+    pub fn intersects(&self, other: Bounds) -> bool {
+        !self.intersection(other).is_empty()
     }
 
-    pub fn collide_part(&self, other: Bounds) -> bool {
-        let a = other.min_x < self.min_x && self.min_x < other.max_x;
-        let b = other.min_x < self.max_x && self.max_x < other.max_x;
-        let c = other.min_y < self.min_y && self.min_y < other.max_y;
-        let d = other.min_y < self.max_y && self.max_y < other.max_y;
+    pub fn contains(&self, x: isize, y: isize) -> bool {
+        self.min_x <= x && x < self.max_x && self.min_y <= y && y < self.max_y
+    }
 
-        (a && c) || (a && d) || (b && c) || (b && d)
+    // Whether every tile of `other` is inside, which holds for the empty rectangle.
+    pub fn contains_rect(&self, other: Bounds) -> bool {
+        other.is_empty() || (self.min_x <= other.min_x && other.max_x <= self.max_x && self.min_y <= other.min_y && other.max_y <= self.max_y)
     }
 
-    pub fn collide(&self, other: Bounds) -> bool {
-        self.min_x < other.min_x + (other.max_x - other.min_x) &&
-        self.min_x + (self.max_x - self.min_x) > other.min_x && 
-        self.min_y < other.min_y + (other.max_y - other.min_y) &&
-        self.min_y + (self.max_y - self.min_y) > other.min_y
+    // Grows every side by `margin` tiles, a negative margin shrinks them.
+    pub fn expand(&self, margin: isize) -> Self {
+        if self.is_empty() {
+            return Bounds::new();
+        }
+        Bounds {
+            min_x: self.min_x - margin,
+            max_x: self.max_x + margin,
+            min_y: self.min_y - margin,
+            max_y: self.max_y + margin,
+        }.normalized()
+    }
 
+    pub fn move_rel(&self, x: isize, y: isize) -> Self {
+        if self.is_empty() {
+            return Bounds::new();
+        }
+        Bounds {
+            min_x: self.min_x + x,
+            max_x: self.max_x + x,
+            min_y: self.min_y + y,
+            max_y: self.max_y + y,
+        }
     }
-}
\ No newline at end of file
+
+    // Every tile inside, row by row.
+    pub fn tiles(&self) -> impl Iterator<Item = (isize, isize)> {
+        let (xs, ys) = if self.is_empty() { (0..0, 0..0) } else { (self.min_x..self.max_x, self.min_y..self.max_y) };
+        ys.flat_map(move |y| xs.clone().map(move |x| (x, y)))
+    }
+
+    // The corners turn around the centre of the pivot tile, so every tile inside ends up where
+    // `Position::rotate_around` puts it. Offsets are counted in half tiles to stay on whole numbers.
+    pub fn rotate_around(&self, (px, py): (isize, isize), rotation: Rotation) -> Self {
+        if self.is_empty() {
+            return Bounds::new();
+        }
+        let corner = |x: isize, y: isize| {
+            let (dx, dy) = rotation.turn((2 * (x - px) - 1, 2 * (y - py) - 1));
+            (px + (dx + 1) / 2, py + (dy + 1) / 2)
+        };
+        let (ax, ay) = corner(self.min_x, self.min_y);
+        let (bx, by) = corner(self.max_x, self.max_y);
+        Bounds {
+            min_x: min(ax, bx),
+            max_x: max(ax, bx),
+            min_y: min(ay, by),
+            max_y: max(ay, by),
+        }
+    }
+
+    // Mirrors from left to right over the column `px`, like `Position::mirror_around`.
+    pub fn mirror_around(&self, px: isize) -> Self {
+        if self.is_empty() {
+            return Bounds::new();
+        }
+        Bounds {
+            min_x: 2 * px + 1 - self.max_x,
+            max_x: 2 * px + 1 - self.min_x,
+            ..*self
+        }
+    }
+}
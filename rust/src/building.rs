@@ -1,4 +1,4 @@
-use crate::position::Position;
+use crate::{position::Position, rotation::Rotation};
 
 
 
@@ -6,7 +6,8 @@ use crate::position::Position;
 pub enum Building {
     Assembler(String),
     Furnace,
-    ChemicalLab,
+    ChemicalLab(String),
+    Refinery(String),
     Belt,
     Pole,
     Inserter,
@@ -14,6 +15,10 @@ pub enum Building {
     Miner,
     Pipe,
     PipeTunnel,
+    BeltTunnel,
+    Splitter,
+    Beacon,
+    Other(String),
 }
 
 impl Building {
@@ -26,9 +31,14 @@ impl Building {
             Building::Inserter => (1, 1),
             Building::LongInserter => (1, 1),
             Building::Miner => (3, 3),
-            Building::ChemicalLab => (3, 3),
+            Building::ChemicalLab(_) => (3, 3),
+            Building::Refinery(_) => (5, 5),
             Building::Pipe => (1, 1),
             Building::PipeTunnel => (1, 1),
+            Building::BeltTunnel => (1, 1),
+            Building::Splitter => (2, 1),
+            Building::Beacon => (3, 3),
+            Building::Other(_) => (1, 1),
         }
     }
 
@@ -41,9 +51,47 @@ impl Building {
             Self::Inserter => (0, 0),
             Self::LongInserter => (0, 0),
             Self::Miner => (1, 1),
-            Self::ChemicalLab => (1, 1),
+            Self::ChemicalLab(_) => (1, 1),
+            Self::Refinery(_) => (2, 2),
             Self::Pipe => (0, 0),
             Self::PipeTunnel => (0, 0),
+            Self::BeltTunnel => (0, 0),
+            // Splitters sit on the edge between their two tiles, which is the position of the right one.
+            Self::Splitter => (1, 0),
+            Self::Beacon => (1, 1),
+            Self::Other(_) => (0, 0),
+        }
+    }
+
+    // Size and origin when facing `rotation`, turning sideways swaps width and height.
+    pub fn rotated_size(&self, rotation: Rotation) -> (isize, isize) {
+        let (w, h) = self.size();
+        if matches!(rotation.cardinal(), Rotation::East | Rotation::West) { (h, w) } else { (w, h) }
+    }
+
+    pub fn rotated_origin(&self, rotation: Rotation) -> (isize, isize) {
+        let (x, y) = self.origin();
+        if matches!(rotation.cardinal(), Rotation::East | Rotation::West) { (y, x) } else { (x, y) }
+    }
+
+    // Inverse of `Entity::from`, entities we know nothing about become `Other`.
+    pub fn from_name(name: &str, recipe: &str) -> Self {
+        match name {
+            "assembling-machine-1" | "assembling-machine-2" | "assembling-machine-3" => Self::Assembler(recipe.to_string()),
+            "stone-furnace" | "steel-furnace" | "electric-furnace" => Self::Furnace,
+            "chemical-lab" | "chemical-plant" => Self::ChemicalLab(recipe.to_string()),
+            "oil-refinery" => Self::Refinery(recipe.to_string()),
+            "transport-belt" | "fast-transport-belt" | "express-transport-belt" => Self::Belt,
+            "small-iron-electric-pole" | "small-electric-pole" | "medium-electric-pole" | "big-electric-pole" | "substation" => Self::Pole,
+            "burner-inserter" | "inserter" | "fast-inserter" | "stack-inserter" => Self::Inserter,
+            "long-handed-inserter" => Self::LongInserter,
+            "miner" | "electric-mining-drill" | "pumpjack" => Self::Miner,
+            "pipe" => Self::Pipe,
+            "pipe-to-ground" => Self::PipeTunnel,
+            "underground-belt" | "fast-underground-belt" | "express-underground-belt" => Self::BeltTunnel,
+            "splitter" | "fast-splitter" | "express-splitter" => Self::Splitter,
+            "beacon" => Self::Beacon,
+            _ => Self::Other(name.to_string()),
         }
     }
 }
@@ -0,0 +1,204 @@
+use std::{collections::BTreeMap, fs, io::{self, Read}, path::PathBuf};
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+use rust::{astar::Region, bounds::Bounds, entity::Blueprint, machines::MachineConfig, planner::Plan, recipes::{self, Recipe, RecipeDb}, synthesize};
+#[cfg(feature = "spring")]
+use rust::spring_system::SpringSystem;
+#[cfg(feature = "tree")]
+use rust::tree_generator;
+
+#[derive(Parser)]
+#[command(about = "Generates Factorio blueprints")]
+pub struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    #[command(about = "Lay out a factory making an item")]
+    Generate(Generate),
+    #[command(about = "Turn blueprint json into a blueprint string")]
+    Encode {
+        #[arg(help = "File with the json, read from stdin when left out")]
+        input: Option<PathBuf>,
+        #[command(flatten)]
+        output: Output,
+    },
+    #[command(about = "Turn a blueprint string into json")]
+    Decode {
+        #[arg(help = "File with the blueprint string, read from stdin when left out")]
+        input: Option<PathBuf>,
+        #[command(flatten)]
+        output: Output,
+    },
+    #[command(about = "Count the entities of a blueprint string and measure its size")]
+    Inspect {
+        #[arg(help = "File with the blueprint string, read from stdin when left out")]
+        input: Option<PathBuf>,
+        #[arg(short, long, help = "Write to this file instead of stdout")]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Generator {
+    // Rows of machines, one per step of the plan.
+    Row,
+    // Depth first search placing one building at a time.
+    #[cfg(feature = "tree")]
+    Tree,
+    // Machines pulled together by their dependencies, only the machines are placed.
+    #[cfg(feature = "spring")]
+    Spring,
+}
+
+#[derive(Args)]
+struct Generate {
+    #[arg(help = "Item to make, by its internal name like fast-inserter, or a recipe name like advanced-oil-processing")]
+    item: String,
+    #[arg(short, long, value_enum, default_value = "row")]
+    generator: Generator,
+    #[arg(short, long, default_value = "1", value_parser = parse_rate, help = "Items per second, or per minute with a /min suffix like 90/min")]
+    rate: f64,
+    #[arg(long = "machine", help = "Machine to build the recipes it can craft with, can be repeated")]
+    machines: Vec<String>,
+    #[arg(long, help = "Fastest belt to use, like fast-transport-belt")]
+    belt: Option<String>,
+    #[arg(long, allow_hyphen_values = true, value_parser = parse_area, help = "Tiles the layout may use, from x0,y0 up to but not including x1,y1")]
+    area: Option<Bounds>,
+    #[cfg(feature = "spring")]
+    #[arg(long, default_value = "0", help = "Seed for the random start of the spring system")]
+    seed: u64,
+    #[arg(long, help = "Recipe json to use instead of the built in recipes")]
+    recipes: Option<PathBuf>,
+    #[command(flatten)]
+    output: Output,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    String,
+    Json,
+}
+
+#[derive(Args)]
+struct Output {
+    #[arg(short, long, help = "Write to this file instead of stdout")]
+    output: Option<PathBuf>,
+    #[arg(short, long, value_enum, help = "Blueprint string or pretty json, defaults to what the command produces")]
+    format: Option<Format>,
+}
+
+impl Output {
+    fn write_blueprint(&self, blueprint: &Blueprint, default: Format) -> Result<(), String> {
+        match self.format.unwrap_or(default) {
+            Format::String => write(&self.output, &blueprint.serialize()),
+            Format::Json => write(&self.output, &blueprint.to_json()),
+        }
+    }
+}
+
+fn write(output: &Option<PathBuf>, text: &str) -> Result<(), String> {
+    match output {
+        Some(path) => fs::write(path, format!("{}\n", text)).map_err(|e| format!("Can not write {}: {}", path.display(), e)),
+        None => {
+            println!("{}", text);
+            Ok(())
+        }
+    }
+}
+
+fn read(input: &Option<PathBuf>) -> Result<String, String> {
+    match input {
+        Some(path) => fs::read_to_string(path).map_err(|e| format!("Can not read {}: {}", path.display(), e)),
+        None => {
+            let mut text = String::new();
+            io::stdin().read_to_string(&mut text).map_err(|e| format!("Can not read stdin: {}", e))?;
+            Ok(text)
+        }
+    }
+}
+
+fn parse_rate(rate: &str) -> Result<f64, String> {
+    let (amount, factor) = match rate.split_once('/') {
+        None => (rate, 1.0),
+        Some((amount, "s")) => (amount, 1.0),
+        Some((amount, "min")) => (amount, 1.0 / 60.0),
+        Some((_, unit)) => return Err(format!("Unknown unit {}, use s or min", unit)),
+    };
+    let amount: f64 = amount.trim().parse().map_err(|_| format!("{} is not a number", amount))?;
+    if amount <= 0.0 || !amount.is_finite() {
+        return Err(String::from("The rate has to be positive"));
+    }
+    Ok(amount * factor)
+}
+
+fn parse_area(area: &str) -> Result<Bounds, String> {
+    let corners = area.split(',').map(|c| c.trim().parse().map_err(|_| format!("{} is not a number", c))).collect::<Result<Vec<isize>, _>>()?;
+    let [min_x, min_y, max_x, max_y] = corners[..] else {
+        return Err(String::from("The area takes four numbers, x0,y0,x1,y1"));
+    };
+    let bounds = Bounds { min_x, max_x, min_y, max_y };
+    if bounds.is_empty() {
+        return Err(String::from("The area has no tiles, x1 and y1 have to be larger than x0 and y0"));
+    }
+    Ok(bounds)
+}
+
+pub fn run(cli: Cli) -> Result<(), String> {
+    match cli.command {
+        Command::Generate(args) => args.output.write_blueprint(&generate(&args)?, Format::String),
+        Command::Encode { input, output } => output.write_blueprint(&Blueprint::from_json(&read(&input)?)?, Format::String),
+        Command::Decode { input, output } => output.write_blueprint(&Blueprint::decode(&read(&input)?)?, Format::Json),
+        Command::Inspect { input, output } => write(&output, &inspect(&Blueprint::decode(&read(&input)?)?)),
+    }
+}
+
+fn generate(args: &Generate) -> Result<Blueprint, String> {
+    if let Some(path) = &args.recipes {
+        recipes::install(RecipeDb::load(&path.to_string_lossy())?)?;
+    }
+    let recipe = Recipe::producer(&args.item).or_else(|| Recipe::by_name(&args.item)).ok_or(format!("No recipe makes {}", args.item))?;
+
+    let mut config = MachineConfig::default();
+    for machine in &args.machines {
+        config.use_machine(machine)?;
+    }
+    if let Some(belt) = &args.belt {
+        config.set_belt_tier(belt)?;
+    }
+    if let Some(area) = args.area {
+        config.set_region(Region::Rect(area));
+    }
+
+    let blueprint = match args.generator {
+        Generator::Row => {
+            let plan = Plan::new(recipe, args.rate, &config)?;
+            let grid = synthesize::synth(&plan).map_err(|e| e.to_string())?;
+            grid.to_powered_blueprint(config.pole()).map_err(|e| e.to_string())?
+        }
+        #[cfg(feature = "tree")]
+        Generator::Tree => tree_generator::generate_recipe(recipe, args.rate, config).map_err(|e| e.to_string())?,
+        #[cfg(feature = "spring")]
+        Generator::Spring => SpringSystem::new(&Plan::new(recipe, args.rate, &config)?, config, args.seed).map_err(|e| e.to_string())?.partial_blueprint(),
+    };
+    Blueprint::decode(&blueprint)
+}
+
+fn inspect(blueprint: &Blueprint) -> String {
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for entity in &blueprint.entities {
+        *counts.entry(&entity.name).or_insert(0) += 1;
+    }
+    let mut lines = vec![format!("{} entities", blueprint.entities.len())];
+    let bounds = blueprint.entities.iter().map(|e| e.bounds()).fold(Bounds::new(), |a, b| a.union(b));
+    if !bounds.is_empty() {
+        lines.push(format!("{} x {} tiles", bounds.width(), bounds.height()));
+    }
+    for (name, count) in counts {
+        lines.push(format!("{:>6} {}", count, name));
+    }
+    lines.join("\n")
+}
@@ -1,68 +1,248 @@
-use std::io::Write;
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
 
 use flate2::Compression;
+use flate2::read::ZlibDecoder;
 use flate2::write::ZlibEncoder;
-use serde::{Serialize, Deserialize};
-use serde_json;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 
+use crate::belts::BeltTier;
 use crate::bounds::Bounds;
 use crate::building::Building;
 use crate::building::Building::*;
+use crate::inserters::InserterTier;
+use crate::machines::{self, Machine};
+use crate::power;
 use crate::position::Position;
 use crate::rotation::Rotation;
 
-#[derive(Serialize, Clone, PartialEq, Eq, Hash, Debug)]
-pub struct EntityPosition {
+// The tile an entity is anchored on, its top left tile moved by the origin of the building.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct TilePosition {
     pub x: isize,
     pub y: isize,
 }
 
-#[derive(Serialize, Clone, PartialEq, Eq, Hash, Debug)]
+// Where the game puts an entity, the centre of its footprint. Sides of odd length put it on a tile centre (x.5),
+// sides of even length on the edge between two tiles.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct EntityPosition {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl EntityPosition {
+    pub fn from_tile(tile: TilePosition, (w, h): (isize, isize), (l, t): (isize, isize)) -> Self {
+        EntityPosition {
+            x: (tile.x - l) as f64 + w as f64 / 2.0,
+            y: (tile.y - t) as f64 + h as f64 / 2.0,
+        }
+    }
+
+    // Rounds to the nearest tile, blueprints from other tools are not always on the grid.
+    pub fn to_tile(self, (w, h): (isize, isize), (l, t): (isize, isize)) -> TilePosition {
+        TilePosition {
+            x: (self.x - w as f64 / 2.0).round() as isize + l,
+            y: (self.y - h as f64 / 2.0).round() as isize + t,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+#[serde(from = "RawEntity", into = "RawEntity")]
 pub struct Entity {
     pub entity_number: usize,
     pub name: String,
-    pub position: EntityPosition,
+    pub position: TilePosition,
     pub direction: usize,
     pub recipe: String,
-    #[serde(skip_serializing)]
+    // Item requests, used for the modules that should go into the entity.
+    pub items: BTreeMap<String, usize>,
+    // Whether an underground belt is the entrance ("input") or the exit ("output").
+    pub kind: String,
     pub building: Building,
+    // Fields we do not model ourselves, kept so they survive a decode/encode round trip.
+    pub extra: Map<String, Value>,
+}
+
+// An entity as it is written in a blueprint.
+#[derive(Serialize, Deserialize, Clone)]
+struct RawEntity {
+    entity_number: usize,
+    name: String,
+    position: EntityPosition,
+    #[serde(default)]
+    direction: usize,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    recipe: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    items: BTreeMap<String, usize>,
+    #[serde(rename = "type", default, skip_serializing_if = "String::is_empty")]
+    kind: String,
+    #[serde(flatten)]
+    extra: Map<String, Value>,
+}
+
+impl From<RawEntity> for Entity {
+    fn from(raw: RawEntity) -> Self {
+        let mut entity = Entity {
+            entity_number: raw.entity_number,
+            building: Building::from_name(&raw.name, &raw.recipe),
+            name: raw.name,
+            position: TilePosition { x: 0, y: 0 },
+            direction: raw.direction,
+            recipe: raw.recipe,
+            items: raw.items,
+            kind: raw.kind,
+            extra: raw.extra,
+        };
+        // The footprint depends on the building and its direction, so those go first.
+        entity.position = raw.position.to_tile(entity.size(), entity.origin());
+        entity
+    }
+}
+
+impl From<Entity> for RawEntity {
+    fn from(entity: Entity) -> Self {
+        RawEntity {
+            entity_number: entity.entity_number,
+            position: entity.entity_position(),
+            name: entity.name,
+            direction: entity.direction,
+            recipe: entity.recipe,
+            items: entity.items,
+            kind: entity.kind,
+            extra: entity.extra,
+        }
+    }
 }
 
 impl Entity {
 
     pub fn from(building: Building) -> Self {
-        match building {
-            Assembler(recipe) => Entity {entity_number: 0, name: String::from("assembling-machine-1"), position: EntityPosition { x: 0, y: 0 }, direction: 0, recipe: recipe.clone(), building: Assembler(recipe)},
-            Furnace => Entity { entity_number: 0, name: String::from("electric-furnace"), position: EntityPosition { x: 0, y: 0 }, direction: 0, recipe: String::new(), building: Furnace },
-            Belt => Entity { entity_number: 0, name: String::from("fast-transport-belt"), position: EntityPosition { x: 0, y: 0 }, direction: 0, recipe: String::new(), building: Belt},
-            Pole => Entity { entity_number: 0, name: String::from("small-iron-electric-pole"), position: EntityPosition { x: 0, y: 0 }, direction: 0, recipe: String::new(), building: Pole},
-            Inserter => Entity { entity_number: 0, name: String::from("fast-inserter"), position: EntityPosition { x: 0, y: 0 }, direction: 0, recipe: String::new(), building: Inserter},
-            LongInserter => Entity { entity_number: 0, name: String::from("long-handed-inserter"), position: EntityPosition { x: 0, y: 0 }, direction: 0, recipe: String::new(), building: LongInserter},
-            Miner => Entity { entity_number: 0, name: String::from("miner"), position: EntityPosition { x: 0, y: 0 }, direction: 0, recipe: String::new(), building: Miner},
-            ChemicalLab => Entity { entity_number: 0, name: String::from("chemical-lab"), position: EntityPosition { x: 0, y: 0 }, direction: 0, recipe: String::new(), building: ChemicalLab},
-            Pipe => Entity { entity_number: 0, name: String::from("pipe"), position: EntityPosition { x: 0, y: 0 }, direction: 0, recipe: String::new(), building: Pipe},
-            PipeTunnel => Entity { entity_number: 0, name: String::from("pipe-to-ground"), position: EntityPosition { x: 0, y: 0 }, direction: 0, recipe: String::new(), building: PipeTunnel},
+        let (name, recipe) = match &building {
+            Assembler(recipe) => ("assembling-machine-1", recipe.clone()),
+            Furnace => ("electric-furnace", String::new()),
+            Belt => ("fast-transport-belt", String::new()),
+            Pole => ("small-iron-electric-pole", String::new()),
+            Inserter => ("fast-inserter", String::new()),
+            LongInserter => ("long-handed-inserter", String::new()),
+            Miner => ("electric-mining-drill", String::new()),
+            ChemicalLab(recipe) => ("chemical-plant", recipe.clone()),
+            Refinery(recipe) => ("oil-refinery", recipe.clone()),
+            Pipe => ("pipe", String::new()),
+            PipeTunnel => ("pipe-to-ground", String::new()),
+            BeltTunnel => ("fast-underground-belt", String::new()),
+            Splitter => ("fast-splitter", String::new()),
+            Beacon => ("beacon", String::new()),
+            Other(name) => (name.as_str(), String::new()),
+        };
+
+        Entity {
+            entity_number: 0,
+            name: String::from(name),
+            position: TilePosition { x: 0, y: 0 },
+            direction: 0,
+            recipe,
+            items: BTreeMap::new(),
+            kind: String::new(),
+            building,
+            extra: Map::new(),
         }
     }
 
+    // Uses the chosen machine tier instead of the building defaults.
+    pub fn machine(building: Building, machine: &'static Machine) -> Self {
+        let mut entity = Entity::from(building);
+        entity.name = machine.name.to_string();
+        entity
+    }
+
+    pub fn belt(tier: &'static BeltTier) -> Self {
+        let mut entity = Entity::from(Belt);
+        entity.name = tier.name.to_string();
+        entity
+    }
+
+    pub fn underground(tier: &'static BeltTier, input: bool) -> Self {
+        let mut entity = Entity::from(BeltTunnel);
+        entity.name = tier.underground_name.to_string();
+        entity.kind = String::from(if input { "input" } else { "output" });
+        entity
+    }
+
+    pub fn splitter(tier: &'static BeltTier) -> Self {
+        let mut entity = Entity::from(Splitter);
+        entity.name = tier.splitter_name.to_string();
+        entity
+    }
+
+    pub fn inserter(tier: &'static InserterTier) -> Self {
+        let mut entity = Entity::from(if tier.reach > 1 { LongInserter } else { Inserter });
+        entity.name = tier.name.to_string();
+        entity
+    }
+
+    pub fn size(&self) -> (isize, isize) {
+        if let Some(pole) = power::pole(&self.name) {
+            return (pole.size, pole.size);
+        }
+        machines::machine(&self.name).map(|m| m.size).unwrap_or(self.building.rotated_size(Rotation::from_direction(self.direction)))
+    }
+
+    pub fn origin(&self) -> (isize, isize) {
+        if let Some(pole) = power::pole(&self.name) {
+            return (pole.size / 2, pole.size / 2);
+        }
+        machines::machine(&self.name).map(|m| m.origin()).unwrap_or(self.building.rotated_origin(Rotation::from_direction(self.direction)))
+    }
+
     pub fn bounds(&self) -> Bounds {
-        let (w, h) = self.building.size();
-        let (l, t) = self.building.origin();
-
-        Bounds {
-            min_x: self.position.x - l,
-            max_x: self.position.x - l + w,
-            min_y: self.position.y - t,
-            max_y: self.position.y - t + h,
+        let (w, h) = self.size();
+        let (l, t) = self.origin();
+        Bounds::with_size(self.position.x - l, self.position.y - t, w, h)
+    }
+
+    pub fn entity_position(&self) -> EntityPosition {
+        EntityPosition::from_tile(self.position, self.size(), self.origin())
+    }
+
+    // Turns the footprint around the pivot tile and the entity with it.
+    pub fn rotate_around(&mut self, pivot: (isize, isize), rotation: Rotation) {
+        let footprint = self.bounds().rotate_around(pivot, rotation);
+        self.direction = Rotation::from_direction(self.direction).compose(rotation.cardinal()).assemble();
+        self.move_footprint(footprint);
+    }
+
+    pub fn mirror_around(&mut self, px: isize) {
+        let footprint = self.bounds().mirror_around(px);
+        self.direction = Rotation::from_direction(self.direction).mirror().assemble();
+        self.move_footprint(footprint);
+        // A splitter's left side becomes its right, so its priorities swap sides with it.
+        for key in ["input_priority", "output_priority"] {
+            if let Some(side) = self.extra.get_mut(key) {
+                match side.as_str() {
+                    Some("left") => *side = Value::from("right"),
+                    Some("right") => *side = Value::from("left"),
+                    _ => {}
+                }
+            }
         }
     }
 
-    pub fn set_position(&mut self, mut position: Position) {
+    // The origin depends on the direction, so that has to be set first.
+    fn move_footprint(&mut self, footprint: Bounds) {
+        let (l, t) = self.origin();
+        self.position = TilePosition { x: footprint.min_x + l, y: footprint.min_y + t };
+    }
+
+    pub fn set_position(&mut self, position: Position) {
         match self.building {
             Building::Inserter => { 
                 self.position.x = position.x;
                 self.position.y = position.y;
-                self.direction = position.rotate_left().rotate_left().rotation.assemble(); }
+                self.direction = position.turned(Rotation::South).rotation.assemble(); }
 
             _ => {
                 self.position.x = position.x;
@@ -73,11 +253,19 @@ impl Entity {
     }
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 pub struct Blueprint {
-    entities: Vec<Entity>,
-    item: String,
-    version: usize,
+    pub entities: Vec<Entity>,
+    pub item: String,
+    pub version: usize,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Deserialize)]
+struct BlueprintString {
+    blueprint: Option<Blueprint>,
+    blueprint_book: Option<Value>,
 }
 
 impl Blueprint {
@@ -86,15 +274,45 @@ impl Blueprint {
             entities, 
             item: String::from("blueprint"),
             version: 281479276199938,
+            extra: Map::new(),
         }
     }
 
+    /// Encodes the blueprint as a blueprint string the game can import.
     pub fn serialize(&self) -> String {
         let body = String::from("{\"blueprint\": ") + serde_json::to_string(self).unwrap().as_str() + "}";
-        // println!("body: {}", body);
         let mut e = ZlibEncoder::new(Vec::new(), Compression::default());
         e.write_all(body.as_bytes()).unwrap();
         let compressed = e.finish().unwrap();
         String::from("0") + base64::encode(compressed).as_str()
     }
-}
\ No newline at end of file
+
+    /// Reads a blueprint string exported from the game.
+    pub fn decode(blueprint: &str) -> Result<Self, String> {
+        let blueprint = blueprint.trim();
+        if !blueprint.starts_with('0') {
+            return Err(String::from("Unsupported blueprint string version"));
+        }
+
+        let compressed = base64::decode(&blueprint[1..]).map_err(|e| format!("Invalid base64: {}", e))?;
+        let mut body = String::new();
+        ZlibDecoder::new(compressed.as_slice()).read_to_string(&mut body).map_err(|e| format!("Invalid zlib data: {}", e))?;
+        Self::from_json(&body)
+    }
+
+    /// The json inside a blueprint string, with the blueprint wrapped in an object like the game exports it.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&serde_json::json!({ "blueprint": self })).unwrap()
+    }
+
+    /// Reads json like [`Blueprint::to_json`] writes it.
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let wrapper: BlueprintString = serde_json::from_str(json).map_err(|e| format!("Invalid blueprint json: {}", e))?;
+        let blueprint = match wrapper {
+            BlueprintString { blueprint: Some(blueprint), .. } => blueprint,
+            BlueprintString { blueprint_book: Some(_), .. } => return Err(String::from("Blueprint books are not supported")),
+            _ => return Err(String::from("String does not contain a blueprint")),
+        };
+        Ok(blueprint)
+    }
+}
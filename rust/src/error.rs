@@ -0,0 +1,51 @@
+use std::fmt;
+
+use crate::{position::Position, router::Failure};
+
+// A belt or pipe connection that could not be routed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Unrouted {
+    pub item: String,
+    pub start: Position,
+    pub end: Position,
+    pub reason: Failure,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SynthesisError {
+    Unroutable(Vec<Unrouted>),
+    // The recipe needs a layout that is not supported yet, like a row with more than four solid ingredients.
+    UnsupportedRecipe { recipe: String, reason: String },
+    // Nothing could be put down without running into what is already there.
+    NoPlacement(String),
+    // The request itself is wrong, like an unknown recipe or a rate that is not positive.
+    InvalidInput(String),
+}
+
+impl SynthesisError {
+    pub fn unsupported(recipe: &str, reason: impl Into<String>) -> Self {
+        SynthesisError::UnsupportedRecipe { recipe: recipe.to_string(), reason: reason.into() }
+    }
+}
+
+impl fmt::Display for Unrouted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} from ({}, {}) to ({}, {}): {}", self.item, self.start.x, self.start.y, self.end.x, self.end.y, self.reason)
+    }
+}
+
+impl fmt::Display for SynthesisError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SynthesisError::Unroutable(nets) => {
+                let nets: Vec<String> = nets.iter().map(|net| net.to_string()).collect();
+                write!(f, "Could not route {}", nets.join(", "))
+            }
+            SynthesisError::UnsupportedRecipe { recipe, reason } => write!(f, "Can not build {}: {}", recipe, reason),
+            SynthesisError::NoPlacement(reason) => write!(f, "No placement found: {}", reason),
+            SynthesisError::InvalidInput(reason) => write!(f, "Invalid input: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for SynthesisError {}
@@ -1,29 +1,93 @@
-use std::{collections::HashSet, cmp::{min, max}};
+use std::collections::HashMap;
 
-use crate::{entity::{Entity, Blueprint, self}, rotation::Rotation, building::Building, position::Position, recipes::{Recipe, State}, bounds::Bounds, astar::Astar};
+use crate::{balancers, belts::{self, BeltTier, Lane}, entity::{Entity, Blueprint}, power::{self, Pole}, rotation::Rotation, building::Building, position::Position, recipes::{Recipe, State}, bounds::Bounds, astar::{self, Astar, Costs, Region}, router::{self, Failure, Net}, planner, occupancy::Occupancy, error::{SynthesisError, Unrouted}};
 
 
+// Placements are scored in tiles: what they add to the bounding box, plus this much for every tile of belt
+// estimated between the output of a grid and the input it feeds.
+const ROUTE_WEIGHT: isize = 4;
+// A placed child is only moved again when that lowers its score by at least a fifth.
+const MOVE_GAIN: (isize, isize) = (4, 5);
+
+#[derive(Clone, Debug)]
+pub struct Byproduct {
+    pub item: String,
+    pub amount_per_sec: f64,
+    // The end of the belt or pipe the byproduct leaves on, separate from the output of the main product.
+    pub position: Position,
+    pub state: State,
+}
+
+// An input of one of the rows fed by a step built elsewhere in the layout. It is connected once a grid holds both
+// the input and an exit carrying what it needs.
+#[derive(Clone, Debug)]
+pub struct Demand {
+    pub item: String,
+    pub amount_per_sec: f64,
+    pub position: Position,
+    pub state: State,
+    pub belt: &'static BeltTier,
+}
+
 #[derive(Clone)]
 pub struct Grid {
     entities: Vec<Entity>,
-    reserved: HashSet<Position>,
+    // Tiles taken by the entities of this grid and all of its children.
+    occupied: Occupancy,
+    // Tiles inside the bounds of this grid or one of its children, other grids are placed around them.
+    boxes: Occupancy,
+    // Tiles holding pipes, with the fluid inside.
+    fluids: HashMap<Position, String>,
+    // Items on the left and right lane of belt tiles.
+    lanes: HashMap<Position, [Option<String>; 2]>,
     output: Option<Position>,
+    // What leaves through the outputs when that is not the main product of the recipe, with its state.
+    product: Option<(String, State)>,
+    // Outputs of parallel rows when a single belt can not carry everything.
+    extra_outputs: Vec<Position>,
+    // Where another belt can join the output belt at its upstream end.
+    feed: Option<Position>,
     inputs: Vec<Position>,
+    byproducts: Vec<Byproduct>,
+    demands: Vec<Demand>,
     childs: Vec<Grid>,
+    // Belt connections waiting for `route_nets`.
+    nets: Vec<Net>,
+    // Children whose only connection is a net that is not routed yet, with the index of that net in `nets`.
+    // They can still be moved when a later placement makes a better spot for them.
+    movable: Vec<(usize, usize)>,
     recipe: Recipe,
     bounds: Bounds,
+    belt: &'static BeltTier,
+    // What paths pay for, shared by belts and pipes.
+    costs: Costs,
+    // Where belts and pipes may go, in the coordinates of this grid.
+    region: Region,
 }
 
 impl Grid {
     pub fn new(recipe: Recipe) -> Self {
         Grid {
             entities: Vec::new(),
-            reserved: HashSet::new(),
+            occupied: Occupancy::new(),
+            boxes: Occupancy::new(),
+            fluids: HashMap::new(),
+            lanes: HashMap::new(),
             output: None,
+            product: None,
+            extra_outputs: Vec::new(),
+            feed: None,
             inputs: Vec::new(),
+            byproducts: Vec::new(),
+            demands: Vec::new(),
             childs: Vec::new(),
+            nets: Vec::new(),
+            movable: Vec::new(),
             recipe,
             bounds: Bounds::new(),
+            belt: belts::fastest(),
+            costs: Costs::default(),
+            region: Region::Everywhere,
         }
     }
 
@@ -35,15 +99,90 @@ impl Grid {
         self.output = Some(output);
     }
 
+    pub fn output(&self) -> Option<Position> {
+        self.output
+    }
+
+    pub fn extra_outputs(&self) -> &[Position] {
+        &self.extra_outputs
+    }
+
+    pub fn add_extra_output(&mut self, output: Position) {
+        self.extra_outputs.push(output);
+    }
+
+    pub fn set_feed(&mut self, feed: Position) {
+        self.feed = Some(feed);
+    }
+
+    pub fn set_belt(&mut self, belt: &'static BeltTier) {
+        self.belt = belt;
+    }
+
+    pub fn belt(&self) -> &'static BeltTier {
+        self.belt
+    }
+
+    pub fn set_costs(&mut self, costs: Costs) {
+        self.costs = costs;
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+    }
+
+    // Moves the grid into the corner of `area` when it is not inside already.
+    pub fn move_into(&mut self, area: Bounds) {
+        let b = self.full_bounding_box();
+        if !area.contains_rect(b) {
+            self.move_relative(area.min_x - b.min_x, area.min_y - b.min_y);
+        }
+    }
+
+    // Placed grids share the coordinates of the grid they are placed in, and with them its region.
+    fn share_region(&mut self, region: &Region) {
+        self.region = region.clone();
+        for child in &mut self.childs {
+            child.share_region(region);
+        }
+    }
+
+    // The belt into the input comes from behind it, other grids keep their distance from both tiles like from the box.
     pub fn add_input(&mut self, input: Position) {
+        for tile in input_tiles(input) {
+            self.boxes.insert(tile.x, tile.y);
+        }
+        self.inputs.push(input);
+    }
+
+    // The tile where the pipe will end and the one it is approached from already count as holding the fluid,
+    // so other fluids keep their distance and a pipe-to-ground can always surface right in front of the input.
+    pub fn add_fluid_input(&mut self, input: Position, fluid: &str) {
+        let approach = input.forward();
+        self.fluids.insert(Position::north(input.x, input.y), fluid.to_string());
+        self.fluids.insert(Position::north(approach.x, approach.y), fluid.to_string());
         self.inputs.push(input);
     }
 
-    fn move_absolute(&mut self, x: isize, y: isize) {
-        let current_min_x = self.bounds.min_x;
-        let current_min_y = self.bounds.min_y;
+    pub fn add_byproduct(&mut self, byproduct: Byproduct) {
+        self.byproducts.push(byproduct);
+    }
+
+    // Byproducts that still leave this grid, including the ones of its children that were not consumed.
+    pub fn byproducts(&self) -> &[Byproduct] {
+        &self.byproducts
+    }
 
-        self.move_relative(x - current_min_x, y - current_min_y);
+    // Leaves one of the inputs open, to be fed from an exit of another grid by `feed_demands`.
+    pub fn add_demand(&mut self, index: usize, item: &str, state: State, amount_per_sec: f64) -> Result<(), SynthesisError> {
+        let position = self.input(index)?;
+        self.demands.push(Demand { item: item.to_string(), amount_per_sec, position, state, belt: self.belt });
+        Ok(())
+    }
+
+    // Inputs of this grid and its children that are still waiting for an exit to feed them.
+    pub fn demands(&self) -> &[Demand] {
+        &self.demands
     }
 
     fn move_relative(&mut self, x: isize, y: isize) {
@@ -52,131 +191,375 @@ impl Grid {
             entity.position.y += y;
         }
 
-        let mut new_reserved = HashSet::new();
-        for r in &self.reserved {
-            let mut new_pos = r.clone();
-            new_pos.x += x;
-            new_pos.y += y;
-            new_reserved.insert(new_pos);
-        }
-        self.reserved = new_reserved;
+        self.occupied = self.occupied.move_rel(x, y);
+        self.boxes = self.boxes.move_rel(x, y);
 
-        if let Some(mut output) = self.output.clone() {
+        self.fluids = self.fluids.drain().map(|(p, fluid)| (p.move_rel(x, y), fluid)).collect();
+        self.lanes = self.lanes.drain().map(|(p, lanes)| (p.move_rel(x, y), lanes)).collect();
+
+        if let Some(mut output) = self.output {
             output.x += x;
             output.y += y;
             self.output = Some(output);
         }
 
+        if let Some(mut feed) = self.feed {
+            feed.x += x;
+            feed.y += y;
+            self.feed = Some(feed);
+        }
+
+        for output in &mut self.extra_outputs {
+            output.x += x;
+            output.y += y;
+        }
+
         for input in &mut self.inputs {
             input.x += x;
             input.y += y;
         }
 
+        for byproduct in &mut self.byproducts {
+            byproduct.position.x += x;
+            byproduct.position.y += y;
+        }
+
+        for demand in &mut self.demands {
+            demand.position.x += x;
+            demand.position.y += y;
+        }
+
+        self.nets = self.nets.iter().map(|net| net.move_rel(x, y)).collect();
+        self.region = self.region.move_rel(x, y);
+
         for child_grid in &mut self.childs {
             child_grid.move_relative(x, y);
         }
 
-        self.bounds.min_x += x;
-        self.bounds.max_x += x;
-        self.bounds.min_y += y;
-        self.bounds.max_y += y;
+        self.bounds = self.bounds.move_rel(x, y);
+    }
+
+    // Turns the grid and its children a quarter turn at a time, keeping the top left corner where it is.
+    pub fn rotate(&mut self, rotation: Rotation) {
+        let b = self.full_bounding_box();
+        self.transform(Transform::Rotate((b.min_x, b.min_y), rotation.cardinal()));
+        self.realign(b);
+    }
+
+    // Mirrors the grid and its children from left to right, keeping the top left corner where it is.
+    pub fn mirror(&mut self) {
+        let b = self.full_bounding_box();
+        self.transform(Transform::Mirror(b.min_x));
+        self.realign(b);
+    }
+
+    // Machines with fluid boxes can not be mirrored in the game, their pipes would end up on the wrong inputs.
+    pub fn mirrorable(&self) -> bool {
+        self.fluid_tiles().is_empty()
+    }
+
+    // The grid in all four rotations, followed by the mirrored ones when it can be mirrored.
+    pub fn orientations(&self) -> Vec<Grid> {
+        let mut mirrored = self.clone();
+        mirrored.mirror();
+        let bases = if self.mirrorable() { vec![self.clone(), mirrored] } else { vec![self.clone()] };
+        bases.iter().flat_map(|base| Rotation::CARDINAL.map(|rotation| {
+            let mut grid = base.clone();
+            grid.rotate(rotation);
+            grid
+        })).collect()
+    }
+
+    fn realign(&mut self, before: Bounds) {
+        let after = self.full_bounding_box();
+        self.move_relative(before.min_x - after.min_x, before.min_y - after.min_y);
+    }
+
+    fn transform(&mut self, t: Transform) {
+        for entity in &mut self.entities {
+            t.entity(entity);
+        }
+        self.occupied = t.tiles(&self.occupied);
+        self.boxes = t.tiles(&self.boxes);
+        self.fluids = self.fluids.drain().map(|(p, fluid)| (t.tile(p), fluid)).collect();
+        // Mirroring swaps the left and right lane of every belt.
+        self.lanes = self.lanes.drain().map(|(p, [left, right])| match t {
+            Transform::Rotate(..) => (t.tile(p), [left, right]),
+            Transform::Mirror(_) => (t.tile(p), [right, left]),
+        }).collect();
+
+        self.output = self.output.map(|p| t.position(p));
+        self.feed = self.feed.map(|p| t.position(p));
+        for p in self.extra_outputs.iter_mut().chain(&mut self.inputs) {
+            *p = t.position(*p);
+        }
+        for byproduct in &mut self.byproducts {
+            byproduct.position = t.position(byproduct.position);
+        }
+        for demand in &mut self.demands {
+            demand.position = t.position(demand.position);
+        }
+
+        self.nets = self.nets.iter().map(|net| Net {
+            start: t.position(net.start),
+            end: t.position(net.end),
+            avoid: t.tiles(&net.avoid),
+            ..net.clone()
+        }).collect();
+        self.region = match &self.region {
+            Region::Everywhere => Region::Everywhere,
+            Region::Rect(b) => Region::Rect(t.bounds(*b)),
+            Region::Mask(tiles) => Region::Mask(t.tiles(tiles)),
+        };
 
-        
+        for child_grid in &mut self.childs {
+            child_grid.transform(t);
+        }
+        self.bounds = t.bounds(self.bounds);
     }
 
-    fn extend_grid(&mut self, other: Grid) {
+    fn extend_grid(&mut self, mut other: Grid) {
+        other.share_region(&self.region);
+        self.occupied.extend(&other.occupied);
+        self.boxes.extend(&other.boxes);
         self.childs.push(other);
-        // for entity in &other.entities {
-        //     self.add_entity(entity.clone());
-        // }
     }
 
     fn full_bounding_box(&self) -> Bounds {
         let mut b = self.bounds;
         for child_grid in &self.childs {
-            b = b.combine(child_grid.full_bounding_box());
+            b = b.union(child_grid.full_bounding_box());
         }
         b
     }
 
-    fn individual_boxes(&self) -> Vec<Bounds> {
-        let mut result = Vec::new();
-        result.push(self.bounds);
-        for c in &self.childs {
-            result.append(&mut c.individual_boxes());
-        }
-        result
-    }
-
-
-    pub fn add(&mut self, mut other: Grid, index: usize) {
-        // Try to fit this grid anywhere and route to it. // Currently used a very very crude way of placing grids.
-        let original_bounding_box = self.full_bounding_box();
-        let mut other_bb = other.full_bounding_box();
-        other_bb.min_x -= 2; // Compensate for routing space.
-        let individual_boxes = self.individual_boxes();
-
-        let mut best_position = (2, original_bounding_box.max_y);
-        let mut best_area = 99999999;
-        for x in 1..original_bounding_box.max_x + 2{
-            for y in 0..original_bounding_box.max_y + 2 {
-                let mut b = other_bb.clone();
-                b.min_x += x;
-                b.max_x += x;
-                b.min_y += y;
-                b.max_y += y;
-
-                let mut collided = false;
-                for individual_b in &individual_boxes {
-                    
-                    if b.collide(individual_b.clone()) {
-                        collided = true;
-                        break;
-                    }
-                }
-                if collided {
-                    continue;
-                }
 
+    // Connects the output of `other` to one of the inputs. When `other` makes `spare` more than the input takes,
+    // its output is split and the rest leaves this grid as an exit, for other consumers to take.
+    pub fn add(&mut self, mut other: Grid, index: usize, spare: f64) -> Result<(), SynthesisError> {
+        let input = self.input(index)?;
+        let (byproducts, demands) = (self.byproducts.len(), self.demands.len());
+        self.place(&mut other, input)?;
+        let output = other.output.ok_or_else(|| no_output(&other))?;
+        let ((item, state), belt, recipe) = (other.product(), other.belt, other.recipe);
+        let mut outputs = vec![output];
+        outputs.append(&mut other.extra_outputs);
+        self.extend_grid(other);
+
+        // The input takes a single belt, so parallel rows are merged first.
+        if outputs.len() == 1 {
+            self.feed(output, input, &item, state, belt, spare)?;
+            // Byproducts leave through this grid at positions of the child, so such children stay where they are.
+            if state.is_solid() && spare <= 0.0 && self.byproducts.len() == byproducts && self.demands.len() == demands {
+                self.movable.push((self.childs.len() - 1, self.nets.len() - 1));
+                self.improve_placements();
+            }
+            return Ok(());
+        }
+        if !state.is_solid() {
+            return Err(SynthesisError::unsupported(recipe.name(), format!("no balancer merges the outputs of {} parallel rows", outputs.len())));
+        }
+        let merged = self.merge(outputs, input, &item, belt, recipe)?;
+        self.feed(merged, input, &item, state, belt, spare)
+    }
 
-                let new_area = b.combine(original_bounding_box).area();
-                if new_area < best_area {
-                    best_area = new_area;
-                    best_position = (x, y);
-                }
+    // Connects open inputs of the children to exits of other children carrying what they need, in the order the inputs
+    // were left open. Exits that carry more than the input takes are split.
+    pub fn feed_demands(&mut self) -> Result<(), SynthesisError> {
+        let mut open = Vec::new();
+        for demand in std::mem::take(&mut self.demands) {
+            let supply = self.byproducts.iter().position(|b| b.item == demand.item && planner::leftover(demand.amount_per_sec, b.amount_per_sec) <= 0.0);
+            let Some(supply) = supply else {
+                open.push(demand);
+                continue;
+            };
+            let supply = self.byproducts.remove(supply);
+            let spare = planner::leftover(supply.amount_per_sec, demand.amount_per_sec);
+            self.feed(supply.position, demand.position, &demand.item, demand.state, demand.belt, spare)?;
+        }
+        self.demands = open;
+        Ok(())
+    }
 
+    // Connects `start` to `end`, leaving `spare` of the item as an exit. Pipes can branch off anywhere, so the end of a
+    // pipe stays an exit as it is, a belt goes through a splitter whose other output becomes the exit.
+    fn feed(&mut self, start: Position, end: Position, item: &str, state: State, belt: &'static BeltTier, spare: f64) -> Result<(), SynthesisError> {
+        if spare <= 0.0 {
+            return self.connect(start, end, item, state, belt);
+        }
+        let (position, state) = match state {
+            State::Liquid => {
+                self.connect(start, end, item, state, belt)?;
+                (start, state)
             }
+            State::Solid => {
+                let mut splitter = balancers::build(balancers::balancer("1-2").unwrap(), self.recipe, belt);
+                self.place(&mut splitter, end)?;
+                let (output, input, rest) = (splitter.output.unwrap(), splitter.inputs[0], splitter.extra_outputs[0]);
+                self.extend_grid(splitter);
+                self.connect(start, input, item, state, belt)?;
+                self.connect(output, end, item, state, belt)?;
+                (rest, state)
+            }
+        };
+        self.byproducts.push(Byproduct { item: item.to_string(), amount_per_sec: spare, position, state });
+        Ok(())
+    }
+
+    // Adds a row making the same product, which puts its output onto the upstream end of this grid's output belt.
+    pub fn add_feeder(&mut self, mut other: Grid) -> Result<(), SynthesisError> {
+        if self.feed.is_none() || other.feed.is_none() {
+            return Err(SynthesisError::InvalidInput(format!("{:?} has no belt end to feed into", self.recipe)));
         }
-        other.move_absolute(best_position.0, best_position.1);
-        self.extend_grid(other.clone());
-        self.route(other.output.unwrap(), self.inputs[index].clone(), other.recipe.output_state())
+        self.place(&mut other, self.feed.unwrap())?;
+        let output = other.output.ok_or_else(|| no_output(&other))?;
+        let (state, belt) = (other.recipe.output_state(), other.belt);
+        let item = &other.recipe.main_product().name;
+        let feed = self.feed.replace(other.feed.unwrap());
+        self.extend_grid(other);
+        self.connect(output, feed.unwrap(), item, state, belt)
     }
 
-    pub fn add_entity(&mut self, mut other: Entity) {
-        other.entity_number = self.entities.len();
+    // Adds a row making the same product that keeps its own output belt.
+    pub fn add_parallel(&mut self, mut other: Grid) -> Result<(), SynthesisError> {
+        let output = self.output.ok_or_else(|| no_output(self))?;
+        self.place(&mut other, output)?;
+        self.extra_outputs.push(other.output.ok_or_else(|| no_output(&other))?);
+        self.extra_outputs.append(&mut other.extra_outputs);
+        self.extend_grid(other);
+        Ok(())
+    }
+
+    // Merges the belts into one with a balancer placed towards `target`. More belts than one balancer takes are merged
+    // in groups first, whose merged belts are merged again.
+    fn merge(&mut self, outputs: Vec<Position>, target: Position, item: &str, belt: &'static BeltTier, recipe: Recipe) -> Result<Position, SynthesisError> {
+        if outputs.len() == 1 {
+            return Ok(outputs[0]);
+        }
+        let Some(merger) = balancers::merger(outputs.len()) else {
+            let groups = outputs.chunks(balancers::most_merged()).map(|group| self.merge(group.to_vec(), target, item, belt, recipe)).collect::<Result<_, _>>()?;
+            return self.merge(groups, target, item, belt, recipe);
+        };
+        let mut balancer = balancers::build(merger, recipe, belt);
+        self.place(&mut balancer, target)?;
+        let merged = balancer.output.ok_or_else(|| no_output(&balancer))?;
+        let inputs = balancer.inputs.clone();
+        self.extend_grid(balancer);
+        // Outputs close to the balancer go first, so the longer routes can go around them.
+        let mut pairs: Vec<(Position, Position)> = outputs.into_iter().zip(inputs).collect();
+        pairs.sort_by_key(|(output, input)| (output.x - input.x).abs() + (output.y - input.y).abs());
+        for (output, input) in pairs {
+            self.connect(output, input, item, State::Solid, belt)?;
+        }
+        Ok(merged)
+    }
+
+    fn input(&self, index: usize) -> Result<Position, SynthesisError> {
+        self.inputs.get(index).copied().ok_or_else(|| SynthesisError::InvalidInput(format!("{:?} has no input {}", self.recipe, index)))
+    }
 
-        let (w, h) = other.building.size();
-        let (l, t) = other.building.origin();
-
-        for x in 0..w {
-            for y in 0..h {
-                let pos_x = other.position.x;
-                let pos_y = other.position.y;
-                self.reserved.insert(Position {
-                    x: pos_x + x - l,
-                    y: pos_y + y - t,
-                    rotation: Rotation::North,
-                });
+    // Puts the grid where it adds the least to the bounding box and the belt from its output to `target` is short,
+    // trying every orientation of it.
+    fn place(&mut self, other: &mut Grid, target: Position) -> Result<(), SynthesisError> {
+        let full = self.full_bounding_box();
+        if other.output.is_none() {
+            return Err(no_output(other));
+        }
+        if !full.is_empty() {
+            let surroundings = Surroundings { occupied: &self.occupied, boxes: &self.boxes, full, region: &self.region };
+            let mut orientations = other.orientations();
+            let best = orientations.iter().enumerate().filter_map(|(i, grid)| {
+                surroundings.best_offset(grid, grid.output.unwrap(), target).map(|(score, offset)| (score, i, offset))
+            }).min_by_key(|(score, i, _)| (*score, *i));
+            let (_, i, (x, y)) = best.ok_or_else(|| SynthesisError::NoPlacement(format!("no room for {} in the build area", other.recipe.name())))?;
+            *other = orientations.swap_remove(i);
+            other.move_relative(x, y);
+        }
+        // The child's byproducts now leave through this grid, and its open inputs are fed through it.
+        self.byproducts.append(&mut other.byproducts);
+        self.demands.append(&mut other.demands);
+        Ok(())
+    }
+
+    // Moves children that are not connected yet to a better spot, when that clearly lowers their score.
+    fn improve_placements(&mut self) {
+        for k in 0..self.movable.len() {
+            let (child, net) = self.movable[k];
+            let (mut occupied, mut boxes) = (Occupancy::new(), Occupancy::new());
+            let mut full = self.bounds;
+            for entity in &self.entities {
+                occupied.insert_rect(entity.bounds());
+            }
+            boxes.insert_rect(self.bounds);
+            for (_, c) in self.childs.iter().enumerate().filter(|(i, _)| *i != child) {
+                occupied.extend(&c.occupied);
+                boxes.extend(&c.boxes);
+                full = full.union(c.full_bounding_box());
             }
+
+            let surroundings = Surroundings { occupied: &occupied, boxes: &boxes, full, region: &self.region };
+            let (start, end) = (self.nets[net].start, self.nets[net].end);
+            let current = surroundings.score(self.childs[child].full_bounding_box(), start, end);
+            let Some((score, (x, y))) = surroundings.best_offset(&self.childs[child], start, end) else {
+                continue;
+            };
+            if score * MOVE_GAIN.1 >= current * MOVE_GAIN.0 || (x, y) == (0, 0) {
+                continue;
+            }
+            self.childs[child].move_relative(x, y);
+            self.childs[child].share_region(&self.region);
+            self.nets[net].start = start.move_rel(x, y);
+            occupied.extend(&self.childs[child].occupied);
+            boxes.extend(&self.childs[child].boxes);
+            (self.occupied, self.boxes) = (occupied, boxes);
+        }
+    }
+
+    fn product(&self) -> (String, State) {
+        let main = self.recipe.main_product();
+        self.product.clone().unwrap_or_else(|| (main.name.clone(), main.state))
+    }
+
+    // For grids built for one of their byproducts: the byproduct leaves through the outputs and the main product
+    // is left over as a byproduct in its place.
+    pub fn output_byproduct(&mut self, item: &str) -> Result<(), SynthesisError> {
+        let (exits, others): (Vec<Byproduct>, Vec<Byproduct>) = self.byproducts.drain(..).partition(|b| b.item == item);
+        self.byproducts = others;
+        let Some(state) = exits.first().map(|b| b.state) else {
+            return Err(SynthesisError::InvalidInput(format!("{:?} does not make {}", self.recipe, item)));
+        };
+
+        let main = self.recipe.main_product();
+        let outputs: Vec<Position> = self.output.into_iter().chain(self.extra_outputs.drain(..)).collect();
+        let left_over = exits.iter().map(|b| b.amount_per_sec).sum::<f64>() / self.recipe.yield_of(item) / outputs.len() as f64;
+        for output in outputs {
+            self.byproducts.push(Byproduct { item: main.name.clone(), amount_per_sec: left_over, position: output, state: main.state });
         }
+        let mut exits = exits.into_iter().map(|b| b.position);
+        self.output = exits.next();
+        self.extra_outputs = exits.collect();
+        // The belt or pipe of the byproduct has no upstream end to feed.
+        self.feed = None;
+        self.product = Some((item.to_string(), state));
+        Ok(())
+    }
+
+    pub fn add_entity(&mut self, mut other: Entity) {
+        other.entity_number = self.entities.len();
 
-        self.bounds = self.bounds.combine(other.bounds());
+        self.occupied.insert_rect(other.bounds());
+
+        let bounds = self.bounds.union(other.bounds());
+        if bounds != self.bounds {
+            self.bounds = bounds;
+            self.boxes.insert_rect(bounds);
+        }
         self.entities.push(other);
     }
 
     pub fn place_belts(&mut self, height: isize, x_offset: isize, rotation: Rotation, amount: isize) {
-        let mut entity = Entity::from(Building::Belt);
-        
+        let mut entity = Entity::belt(self.belt);
+
         for i in 0..amount {
             entity.direction = rotation.assemble();
             entity.position.x = i + x_offset;
@@ -210,23 +593,78 @@ impl Grid {
     }
 
     pub fn place_belt(&mut self, x: isize, y: isize, rotation: Rotation) {
-        let mut entity = Entity::from(Building::Belt);
+        let mut entity = Entity::belt(self.belt);
         entity.direction = rotation.assemble();
         entity.position.x = x;
         entity.position.y = y;
         self.add_entity(entity);
     }
 
-    pub fn place_pipe_tunnel(&mut self, height: isize, x_offset: isize, rotation: Rotation, amount: isize, skip: isize) {
+    // The rotation of a pipe-to-ground is the side it connects to above ground.
+    pub fn place_pipe_tunnel(&mut self, height: isize, x_offset: isize, rotation: Rotation, amount: isize, skip: isize, fluid: &str) {
         let entity = Entity::from(Building::PipeTunnel);
-        self.place_entity(entity, height, x_offset, rotation, amount, skip);
+        self.place_fluid_entity(entity, height, x_offset, rotation, amount, skip, fluid);
     }
 
-    pub fn place_pipe(&mut self, height: isize, x_offset: isize, rotation: Rotation, amount: isize, skip: isize) {
+    pub fn place_pipe(&mut self, height: isize, x_offset: isize, rotation: Rotation, amount: isize, skip: isize, fluid: &str) {
         let entity = Entity::from(Building::Pipe);
+        self.place_fluid_entity(entity, height, x_offset, rotation, amount, skip, fluid);
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn place_fluid_entity(&mut self, entity: Entity, height: isize, x_offset: isize, rotation: Rotation, amount: isize, skip: isize, fluid: &str) {
+        for i in 0..amount {
+            self.fluids.insert(Position::north(i * skip + x_offset, height), fluid.to_string());
+        }
         self.place_entity(entity, height, x_offset, rotation, amount, skip);
     }
 
+    pub fn fluid_tiles(&self) -> HashMap<Position, String> {
+        let mut tiles = self.fluids.clone();
+        for c in &self.childs {
+            tiles.extend(c.fluid_tiles());
+        }
+        tiles
+    }
+
+    // Marks what one lane of a straight run of belts carries.
+    pub fn set_lane(&mut self, height: isize, x_offset: isize, amount: isize, lane: Lane, item: &str) {
+        for x in x_offset..x_offset + amount {
+            self.lanes.entry(Position::north(x, height)).or_default()[lane.index()] = Some(item.to_string());
+        }
+    }
+
+    pub fn lanes(&self, x: isize, y: isize) -> [Option<String>; 2] {
+        self.lanes.get(&Position::north(x, y)).cloned().unwrap_or_default()
+    }
+
+    pub fn lane_tiles(&self) -> HashMap<Position, [Option<String>; 2]> {
+        let mut tiles = self.lanes.clone();
+        for c in &self.childs {
+            tiles.extend(c.lane_tiles());
+        }
+        tiles
+    }
+
+    // Tiles in front of belt ends that are not connected to anything yet, unless those belts only carry `item`.
+    fn spill_tiles(&self, start: Position, item: &str, lanes: &HashMap<Position, [Option<String>; 2]>, occupied: &Occupancy) -> Occupancy {
+        let mut spill = Occupancy::new();
+        for entity in self.collect_entities() {
+            let open_end = entity.building == Building::Belt || (entity.building == Building::BeltTunnel && entity.kind == "output");
+            let position = Position::north(entity.position.x, entity.position.y);
+            if !open_end || position == Position::north(start.x, start.y) {
+                continue;
+            }
+            let front = Position { rotation: Rotation::from_direction(entity.direction), ..position }.forward();
+            let carries = lanes.get(&position).cloned().unwrap_or_default();
+            let foreign = carries.iter().all(|l| l.is_none()) || carries.iter().flatten().any(|l| l != item);
+            if foreign && !occupied.contains(front.x, front.y) {
+                spill.insert(front.x, front.y);
+            }
+        }
+        spill
+    }
+
     pub fn collect_entities(&self) -> Vec<Entity> {
         let mut entities = self.entities.clone();
         for child in &self.childs {
@@ -240,36 +678,275 @@ impl Grid {
         Blueprint::new(self.collect_entities()).serialize()
     }
 
-    pub fn reserved_tiles(&self) -> HashSet<Position> {
-        let mut tiles = self.reserved.clone();
+    /// The blueprint string of the layout, with poles added to power every machine.
+    pub fn to_powered_blueprint(&self, pole: &'static Pole) -> Result<String, SynthesisError> {
+        let mut entities = self.collect_entities();
+        power::electrify(&mut entities, pole).map_err(SynthesisError::NoPlacement)?;
+        Ok(Blueprint::new(entities).serialize())
+    }
+
+    pub fn occupied(&self) -> &Occupancy {
+        &self.occupied
+    }
+
+    // Belts are only routed by `route_nets`, once all connections are known.
+    pub fn connect(&mut self, start: Position, end: Position, item: &str, state: State, belt: &'static BeltTier) -> Result<(), SynthesisError> {
+        match state {
+            State::Solid => {
+                self.nets.push(Net::new(start, end, item, belt));
+                Ok(())
+            }
+            State::Liquid => self.route_pipe(start, end, item),
+        }
+    }
+
+    fn collect_nets(&mut self) -> Vec<Net> {
+        self.movable.clear();
+        let mut nets: Vec<Net> = self.nets.drain(..).collect();
+        for child in &mut self.childs {
+            nets.append(&mut child.collect_nets());
+        }
+        nets
+    }
+
+    fn pending_ends(&self) -> Occupancy {
+        let mut ends = Occupancy::new();
+        for net in &self.nets {
+            let front = net.start.forward();
+            ends.insert(front.x, front.y);
+            ends.insert(net.end.x, net.end.y);
+        }
+        for demand in &self.demands {
+            ends.insert(demand.position.x, demand.position.y);
+        }
         for c in &self.childs {
-            tiles.extend(c.reserved_tiles());
+            ends.extend(&c.pending_ends());
+        }
+        ends
+    }
+
+    // Pipes next to each other merge, so the route keeps a tile of distance to pipes holding any other fluid.
+    pub fn route_pipe(&mut self, start: Position, end: Position, fluid: &str) -> Result<(), SynthesisError> {
+        let mut evade = self.pending_ends();
+        for (tile, other) in self.fluid_tiles() {
+            if other != fluid {
+                for rotation in Rotation::CARDINAL {
+                    let neighbour = Position { rotation, ..tile }.forward();
+                    evade.insert(neighbour.x, neighbour.y);
+                }
+            }
+        }
+        let mut astar = Astar::new(start, end, &self.occupied);
+        astar.set_evade(evade);
+        astar.set_underground(astar::PIPE_TUNNEL_REACH);
+        astar.set_costs(self.costs);
+        astar.set_region(self.region.clone());
+
+        match astar.astar_pipe() {
+            Ok(path) => {
+                let mut previous = start;
+                for (p, exit) in path.iter().rev() {
+                    if *exit {
+                        let entrance = Position { rotation: p.rotation, ..previous }.forward();
+                        let mut entity = Entity::from(Building::PipeTunnel);
+                        entity.set_position(entrance.turned(Rotation::South));
+                        self.add_fluid_entity(entity, fluid);
+
+                        let mut entity = Entity::from(Building::PipeTunnel);
+                        entity.set_position(*p);
+                        self.add_fluid_entity(entity, fluid);
+                    } else {
+                        let mut entity = Entity::from(Building::Pipe);
+                        entity.set_position(*p);
+                        self.add_fluid_entity(entity, fluid);
+                    }
+                    previous = *p;
+                }
+                Ok(())
+            }
+            Err(reason) => {
+                let reason = Failure::NoPath(reason);
+                Err(SynthesisError::Unroutable(vec![Unrouted { item: fluid.to_string(), start, end, reason }]))
+            }
         }
-        tiles
     }
 
-    pub fn route(&mut self, start: Position, end: Position, state: State) {
-        let astar = Astar::new(start, end, self.reserved_tiles());
+    fn add_fluid_entity(&mut self, entity: Entity, fluid: &str) {
+        self.fluids.insert(Position::north(entity.position.x, entity.position.y), fluid.to_string());
+        self.add_entity(entity);
+    }
+
+    // Routes every pending belt connection of this grid and its children together.
+    pub fn route_nets(&mut self) -> Result<(), SynthesisError> {
+        let mut nets = self.collect_nets();
+        if nets.is_empty() {
+            return Ok(());
+        }
+        let lanes = self.lane_tiles();
+        for net in &mut nets {
+            net.avoid = self.spill_tiles(net.start, &net.item, &lanes, &self.occupied);
+            // Open inputs are fed later, from grids that are not placed yet.
+            for demand in &self.demands {
+                net.avoid.insert(demand.position.x, demand.position.y);
+            }
+        }
 
+        match router::route(&self.occupied, &self.region, &nets, self.costs) {
+            Ok(paths) => {
+                for (net, path) in nets.iter().zip(paths) {
+                    self.place_route(net, &path, &lanes);
+                }
+                Ok(())
+            }
+            Err(failed) => Err(SynthesisError::Unroutable(failed.into_iter().map(|(i, reason)| {
+                let net = &nets[i];
+                Unrouted { item: net.item.clone(), start: net.start, end: net.end, reason }
+            }).collect())),
+        }
+    }
 
-        if let Some(path) = astar.astar_belt() {
-            for p in &path {
-                let mut entity = Entity::from(Building::Belt);
-                entity.position.x = p.x;
-                entity.position.y = p.y;
-                entity.direction = p.rotation.assemble();
+    // The route keeps the lanes of the belt it starts from, or carries the item on both lanes when nothing is known about it.
+    fn place_route(&mut self, net: &Net, path: &[Position], lanes: &HashMap<Position, [Option<String>; 2]>) {
+        let carried = lanes.get(&Position::north(net.start.x, net.start.y)).cloned().unwrap_or([Some(net.item.clone()), Some(net.item.clone())]);
+        // The path runs from the end back to the start, a gap between two steps is an underground pair.
+        let mut previous = net.start;
+        for p in path.iter().rev() {
+            if (p.x - previous.x).abs() + (p.y - previous.y).abs() > 1 {
+                let entrance = previous.forward();
+                let mut entity = Entity::underground(net.belt, true);
+                entity.set_position(entrance);
+                self.add_entity(entity);
+
+                let mut entity = Entity::underground(net.belt, false);
+                entity.set_position(*p);
+                self.add_entity(entity);
+                self.lanes.insert(Position::north(entrance.x, entrance.y), carried.clone());
+            } else {
+                let mut entity = Entity::belt(net.belt);
+                entity.set_position(*p);
                 self.add_entity(entity);
             }
-        } else {
-            println!("Failed blueprint part: {}", self.to_blueprint());
-            panic!()
+            self.lanes.insert(Position::north(p.x, p.y), carried.clone());
+            previous = *p;
         }
     }
 
-    pub fn is_free(&self, mut position: Position) -> bool {
-        position.rotation = Rotation::North;
-        return !self.reserved.contains(&position);
+    pub fn is_free(&self, position: Position) -> bool {
+        !self.occupied.contains(position.x, position.y)
     }
 
 
-}
\ No newline at end of file
+}
+
+fn no_output(grid: &Grid) -> SynthesisError {
+    SynthesisError::InvalidInput(format!("{:?} has no output", grid.recipe))
+}
+
+// The tile of the belt into an input and the one that belt comes from.
+fn input_tiles(input: Position) -> [Position; 2] {
+    [input, input.backward()]
+}
+
+#[derive(Clone, Copy)]
+enum Transform {
+    // Around a pivot tile.
+    Rotate((isize, isize), Rotation),
+    // Over a column.
+    Mirror(isize),
+}
+
+impl Transform {
+    fn position(self, p: Position) -> Position {
+        match self {
+            Transform::Rotate(pivot, rotation) => p.rotate_around(pivot, rotation),
+            Transform::Mirror(px) => p.mirror_around(px),
+        }
+    }
+
+    // Sets of tiles are keyed by positions facing north.
+    fn tile(self, p: Position) -> Position {
+        let p = self.position(p);
+        Position::north(p.x, p.y)
+    }
+
+    fn tiles(self, tiles: &Occupancy) -> Occupancy {
+        tiles.map(|x, y| {
+            let p = self.position(Position::north(x, y));
+            (p.x, p.y)
+        })
+    }
+
+    fn bounds(self, b: Bounds) -> Bounds {
+        match self {
+            Transform::Rotate(pivot, rotation) => b.rotate_around(pivot, rotation),
+            Transform::Mirror(px) => b.mirror_around(px),
+        }
+    }
+
+    fn entity(self, entity: &mut Entity) {
+        match self {
+            Transform::Rotate(pivot, rotation) => entity.rotate_around(pivot, rotation),
+            Transform::Mirror(px) => entity.mirror_around(px),
+        }
+    }
+}
+
+// What a grid is placed around: the tiles taken, the boxes of the grids already there and their bounding box.
+struct Surroundings<'a> {
+    occupied: &'a Occupancy,
+    boxes: &'a Occupancy,
+    full: Bounds,
+    // Only offsets that keep the grid inside the region are tried.
+    region: &'a Region,
+}
+
+impl Surroundings<'_> {
+    fn growth(&self, placed: Bounds) -> isize {
+        placed.union(self.full).area() - self.full.area()
+    }
+
+    // The growth of the bounding box plus the estimated length of the belt, which grows with the share of
+    // taken tiles between both ends because the belt has to go around them.
+    fn score(&self, placed: Bounds, output: Position, target: Position) -> isize {
+        let corridor = Bounds {
+            min_x: output.x.min(target.x),
+            max_x: output.x.max(target.x) + 1,
+            min_y: output.y.min(target.y),
+            max_y: output.y.max(target.y) + 1,
+        };
+        let length = corridor.width() + corridor.height() - 2;
+        let blocked = self.occupied.count_in(corridor) as isize;
+        self.growth(placed) + ROUTE_WEIGHT * (length + length * blocked / corridor.area())
+    }
+
+    // Whether the area overlaps other grids. The tiles kept clear for the belt into `target` do not count, that belt
+    // is the one the placed grid brings.
+    fn crowded(&self, area: Bounds, target: Position) -> bool {
+        let own = input_tiles(target).into_iter().filter(|t| area.contains(t.x, t.y) && self.boxes.contains(t.x, t.y)).count();
+        self.boxes.count_in(area) > own
+    }
+
+    // The best offset to move `grid` by, with its score. Every belt leaving the grid gets a tile of space around it,
+    // and so do the tiles in front of its inputs, which lie outside of its box.
+    fn best_offset(&self, grid: &Grid, output: Position, target: Position) -> Option<(isize, (isize, isize))> {
+        let b = grid.full_bounding_box();
+        let with_inputs = grid.inputs.iter().fold(b, |r, i| r.union(Bounds::with_size(i.x, i.y, 1, 1)));
+        let margin = 2 + grid.extra_outputs.len() as isize + grid.byproducts.len() as isize;
+        let mut best: Option<(isize, (isize, isize))> = None;
+        for x in self.full.min_x - b.width() - margin..=self.full.max_x + margin {
+            for y in self.full.min_y - b.height() - margin..=self.full.max_y + margin {
+                let offset = (x - b.min_x, y - b.min_y);
+                let placed = b.move_rel(offset.0, offset.1);
+                // The belt only adds to the score, so spots that grow the box too much are out already.
+                if best.is_some_and(|(s, _)| self.growth(placed) >= s) || self.crowded(with_inputs.move_rel(offset.0, offset.1).expand(margin), target) || !self.region.contains_rect(placed) {
+                    continue;
+                }
+                let score = self.score(placed, output.move_rel(offset.0, offset.1), target);
+                if best.is_none_or(|(s, _)| score < s) {
+                    best = Some((score, offset));
+                }
+            }
+        }
+        best
+    }
+}
@@ -0,0 +1,43 @@
+use crate::planner;
+
+#[derive(Debug, PartialEq)]
+pub struct InserterTier {
+    pub name: &'static str,
+    // Items per second when dropping from a machine onto a belt, or between machines and chests.
+    pub drop_throughput: f64,
+    // Items per second when picking up from a belt, which is slower as items have to be chased.
+    pub pickup_throughput: f64,
+    // Distance in tiles between the inserter and the tile it picks up from.
+    pub reach: isize,
+    pub electric: bool,
+}
+
+impl InserterTier {
+    pub fn throughput(&self, from_belt: bool) -> f64 {
+        if from_belt { self.pickup_throughput } else { self.drop_throughput }
+    }
+}
+
+// Ordered from cheapest to most expensive, rates are without inserter capacity research.
+pub const INSERTER_TIERS: &[InserterTier] = &[
+    InserterTier { name: "burner-inserter", drop_throughput: 0.6, pickup_throughput: 0.6, reach: 1, electric: false },
+    InserterTier { name: "inserter", drop_throughput: 0.83, pickup_throughput: 0.8, reach: 1, electric: true },
+    InserterTier { name: "long-handed-inserter", drop_throughput: 1.2, pickup_throughput: 1.15, reach: 2, electric: true },
+    InserterTier { name: "fast-inserter", drop_throughput: 2.31, pickup_throughput: 2.31, reach: 1, electric: true },
+    InserterTier { name: "stack-inserter", drop_throughput: 4.62, pickup_throughput: 4.0, reach: 1, electric: true },
+];
+
+pub fn inserter_tier(name: &str) -> Option<&'static InserterTier> {
+    INSERTER_TIERS.iter().find(|i| i.name == name)
+}
+
+// Cheapest electric inserter with the given reach that keeps up with `amount_per_sec` on its own,
+// otherwise the fastest one and how many of them are needed. None when no electric inserter has that reach.
+pub fn inserters_for(amount_per_sec: f64, from_belt: bool, reach: isize) -> Option<(&'static InserterTier, usize)> {
+    let mut candidates = INSERTER_TIERS.iter().filter(|i| i.electric && i.reach == reach);
+    let fastest = candidates.clone().next_back()?;
+    match candidates.find(|i| i.throughput(from_belt) >= amount_per_sec - 1e-9) {
+        Some(tier) => Some((tier, 1)),
+        None => Some((fastest, planner::count(amount_per_sec / fastest.throughput(from_belt)))),
+    }
+}
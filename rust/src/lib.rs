@@ -0,0 +1,53 @@
+//! Generates Factorio blueprints that make an item at a given rate.
+//!
+//! A [`planner::Plan`] works out the machines every step needs from the [`recipes`] database and a
+//! [`machines::MachineConfig`]. [`synthesize::synth`] lays the plan out as rows of machines in a [`grid::Grid`],
+//! whose belts are connected by the [`router`] on top of the [`astar`] path finder. The result is turned into
+//! a blueprint string with the [`entity::Blueprint`] codec.
+//!
+//! The tree search and spring system generators are behind the `tree` and `spring` features, both on by default.
+
+/// Throughput, underground reach and lanes of the belt tiers.
+pub mod belts;
+/// Splitter templates that merge and balance belts.
+pub mod balancers;
+/// Rectangles on the tile grid.
+pub mod bounds;
+/// Kinds of buildings and their footprints.
+pub mod building;
+/// Entities and the blueprint string codec.
+pub mod entity;
+/// Errors of the layout generators.
+pub mod error;
+/// Inserter tiers and how many of them a rate needs.
+pub mod inserters;
+/// Machines, modules and which machine builds which recipe category.
+pub mod machines;
+/// Sets of taken tiles with fast rectangle queries.
+pub mod occupancy;
+/// Machine counts and rates for every step towards an item.
+pub mod planner;
+/// Tiles with a direction.
+pub mod position;
+/// Electric poles and covering machines with power.
+pub mod power;
+/// The recipe database, built in or loaded from json.
+pub mod recipes;
+/// The eight directions, turning between them and mirroring.
+pub mod rotation;
+
+/// Path finding for belts and pipes.
+pub mod astar;
+/// Routing many belt connections at once.
+pub mod router;
+
+/// Layouts under construction, with their belts, pipes and child layouts.
+pub mod grid;
+/// The row generator, which lays a plan out as rows of machines.
+pub mod synthesize;
+/// The spring system generator, which places machines by pulling them towards the machines they supply.
+#[cfg(feature = "spring")]
+pub mod spring_system;
+/// The tree generator, a depth first search placing one building at a time.
+#[cfg(feature = "tree")]
+pub mod tree_generator;
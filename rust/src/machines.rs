@@ -0,0 +1,260 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{astar::Region, belts::{self, BeltTier}, building::Building, power::{self, Pole}, recipes::Recipe, rotation::Rotation};
+
+// Offset of a pipe connection from the machine position.
+pub type Connection = (isize, isize);
+
+#[derive(Debug, PartialEq)]
+pub struct Machine {
+    pub name: &'static str,
+    pub crafting_speed: f64,
+    pub size: (isize, isize),
+    pub module_slots: usize,
+    pub categories: &'static [&'static str],
+    // Tiles next to the machine where pipes connect to its fluidboxes, relative to its position when facing north.
+    pub fluid_inputs: &'static [Connection],
+    pub fluid_outputs: &'static [Connection],
+}
+
+impl Machine {
+    pub fn origin(&self) -> (isize, isize) {
+        (self.size.0 / 2, self.size.1 / 2)
+    }
+
+    pub fn supports(&self, category: &str) -> bool {
+        self.categories.contains(&category)
+    }
+
+    // Fluidbox connections, inputs first, when the machine faces `rotation`.
+    pub fn fluid_connections(&self, rotation: Rotation) -> (Vec<Connection>, Vec<Connection>) {
+        let turn = |offsets: &[Connection]| offsets.iter().map(|o| rotation.turn(*o)).collect();
+        (turn(self.fluid_inputs), turn(self.fluid_outputs))
+    }
+
+    // First rotation that puts all fluid inputs on one side of the machine and all outputs on another.
+    pub fn orientation(&self, inputs: Rotation, outputs: Rotation) -> Option<Rotation> {
+        Rotation::CARDINAL.into_iter().find(|rotation| {
+            let (i, o) = self.fluid_connections(*rotation);
+            i.iter().all(|(x, y)| Rotation::side(*x, *y) == inputs) && o.iter().all(|(x, y)| Rotation::side(*x, *y) == outputs)
+        })
+    }
+}
+
+const ASSEMBLING: &[&str] = &["crafting", "basic-crafting", "advanced-crafting"];
+const ASSEMBLING_WITH_FLUID: &[&str] = &["crafting", "basic-crafting", "advanced-crafting", "crafting-with-fluid"];
+
+pub const MACHINES: &[Machine] = &[
+    Machine { name: "assembling-machine-1", crafting_speed: 0.5, size: (3, 3), module_slots: 0, categories: ASSEMBLING, fluid_inputs: &[], fluid_outputs: &[] },
+    Machine { name: "assembling-machine-2", crafting_speed: 0.75, size: (3, 3), module_slots: 2, categories: ASSEMBLING_WITH_FLUID, fluid_inputs: &[(0, -2)], fluid_outputs: &[(0, 2)] },
+    Machine { name: "assembling-machine-3", crafting_speed: 1.25, size: (3, 3), module_slots: 4, categories: ASSEMBLING_WITH_FLUID, fluid_inputs: &[(0, -2)], fluid_outputs: &[(0, 2)] },
+    Machine { name: "stone-furnace", crafting_speed: 1.0, size: (2, 2), module_slots: 0, categories: &["smelting"], fluid_inputs: &[], fluid_outputs: &[] },
+    Machine { name: "steel-furnace", crafting_speed: 2.0, size: (2, 2), module_slots: 0, categories: &["smelting"], fluid_inputs: &[], fluid_outputs: &[] },
+    Machine { name: "electric-furnace", crafting_speed: 2.0, size: (3, 3), module_slots: 2, categories: &["smelting"], fluid_inputs: &[], fluid_outputs: &[] },
+    Machine { name: "chemical-plant", crafting_speed: 1.0, size: (3, 3), module_slots: 3, categories: &["chemistry"], fluid_inputs: &[(-1, -2), (1, -2)], fluid_outputs: &[(-1, 2), (1, 2)] },
+    Machine { name: "oil-refinery", crafting_speed: 1.0, size: (5, 5), module_slots: 3, categories: &["oil-processing"], fluid_inputs: &[(-1, 3), (1, 3)], fluid_outputs: &[(-2, -3), (0, -3), (2, -3)] },
+    Machine { name: "electric-mining-drill", crafting_speed: 0.5, size: (3, 3), module_slots: 3, categories: &["basic-solid"], fluid_inputs: &[], fluid_outputs: &[] },
+    Machine { name: "pumpjack", crafting_speed: 1.0, size: (3, 3), module_slots: 2, categories: &["basic-fluid"], fluid_inputs: &[], fluid_outputs: &[(1, -2)] },
+];
+
+pub fn machine(name: &str) -> Option<&'static Machine> {
+    MACHINES.iter().find(|m| m.name == name)
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Module {
+    pub name: &'static str,
+    pub speed: f64,
+    pub productivity: f64,
+    pub consumption: f64,
+}
+
+pub const MODULES: &[Module] = &[
+    Module { name: "speed-module", speed: 0.2, productivity: 0.0, consumption: 0.5 },
+    Module { name: "speed-module-2", speed: 0.3, productivity: 0.0, consumption: 0.6 },
+    Module { name: "speed-module-3", speed: 0.5, productivity: 0.0, consumption: 0.7 },
+    Module { name: "productivity-module", speed: -0.05, productivity: 0.04, consumption: 0.4 },
+    Module { name: "productivity-module-2", speed: -0.1, productivity: 0.06, consumption: 0.6 },
+    Module { name: "productivity-module-3", speed: -0.15, productivity: 0.1, consumption: 0.8 },
+    Module { name: "effectivity-module", speed: 0.0, productivity: 0.0, consumption: -0.3 },
+    Module { name: "effectivity-module-2", speed: 0.0, productivity: 0.0, consumption: -0.4 },
+    Module { name: "effectivity-module-3", speed: 0.0, productivity: 0.0, consumption: -0.5 },
+];
+
+pub fn module(name: &str) -> Option<&'static Module> {
+    MODULES.iter().find(|m| m.name == name)
+}
+
+pub const BEACON_MODULE_SLOTS: usize = 2;
+pub const BEACON_EFFECTIVITY: f64 = 0.5;
+// Beacons are placed in a single row above the machines, which reaches at most three machines wide.
+pub const MAX_BEACONS_PER_MACHINE: usize = 3;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ModuleConfig {
+    pub machine: Vec<&'static Module>,
+    // Modules inside every beacon.
+    pub beacon: Vec<&'static Module>,
+    // Number of beacons reaching every machine.
+    pub beacons: usize,
+}
+
+static NO_MODULES: ModuleConfig = ModuleConfig { machine: Vec::new(), beacon: Vec::new(), beacons: 0 };
+
+impl ModuleConfig {
+    pub fn speed_bonus(&self) -> f64 {
+        let machine: f64 = self.machine.iter().map(|m| m.speed).sum();
+        let beacon: f64 = self.beacon.iter().map(|m| m.speed).sum();
+        machine + beacon * BEACON_EFFECTIVITY * self.beacons as f64
+    }
+
+    pub fn productivity_bonus(&self) -> f64 {
+        self.machine.iter().map(|m| m.productivity).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.machine.is_empty() && (self.beacon.is_empty() || self.beacons == 0)
+    }
+
+    // Item request for the blueprint, module name to count.
+    pub fn machine_items(&self) -> BTreeMap<String, usize> {
+        count_items(&self.machine)
+    }
+
+    pub fn beacon_items(&self) -> BTreeMap<String, usize> {
+        count_items(&self.beacon)
+    }
+}
+
+fn count_items(modules: &[&'static Module]) -> BTreeMap<String, usize> {
+    let mut items = BTreeMap::new();
+    for module in modules {
+        *items.entry(module.name.to_string()).or_insert(0) += 1;
+    }
+    items
+}
+
+// Which machine to build for each recipe category, and which modules to put into it per recipe.
+#[derive(Clone, Debug)]
+pub struct MachineConfig {
+    tiers: HashMap<String, &'static Machine>,
+    modules: HashMap<String, ModuleConfig>,
+    // Fastest belt the layouts may use.
+    belt: &'static BeltTier,
+    pole: &'static Pole,
+    // Where the layout may go, in blueprint coordinates.
+    region: Region,
+}
+
+impl Default for MachineConfig {
+    fn default() -> Self {
+        let mut config = MachineConfig { tiers: HashMap::new(), modules: HashMap::new(), belt: belts::fastest(), pole: power::pole("medium-electric-pole").unwrap(), region: Region::Everywhere };
+        for category in ASSEMBLING {
+            config.set_tier(category, "assembling-machine-1").unwrap();
+        }
+        config.set_tier("crafting-with-fluid", "assembling-machine-2").unwrap();
+        config.set_tier("smelting", "electric-furnace").unwrap();
+        config
+    }
+}
+
+impl MachineConfig {
+    pub fn set_tier(&mut self, category: &str, machine_name: &str) -> Result<(), String> {
+        let m = machine(machine_name).ok_or(format!("Unknown machine {}", machine_name))?;
+        if !m.supports(category) {
+            return Err(format!("{} can not craft {} recipes", machine_name, category));
+        }
+        self.tiers.insert(category.to_string(), m);
+        Ok(())
+    }
+
+    // Builds every category the machine can craft with it.
+    pub fn use_machine(&mut self, machine_name: &str) -> Result<(), String> {
+        let m = machine(machine_name).ok_or(format!("Unknown machine {}", machine_name))?;
+        for category in m.categories {
+            self.tiers.insert(category.to_string(), m);
+        }
+        Ok(())
+    }
+
+    // Checks the modules against the machine currently chosen for the recipe, the plan checks them again for the final one.
+    pub fn set_modules(&mut self, recipe: &str, modules: ModuleConfig) -> Result<(), String> {
+        let r = Recipe::by_name(recipe).ok_or(format!("Unknown recipe {}", recipe))?;
+        if let Some(machine) = self.machine_for_recipe(r).filter(|m| modules.machine.len() > m.module_slots) {
+            return Err(format!("{} only has {} module slots", machine.name, machine.module_slots));
+        }
+        if modules.machine.iter().any(|m| m.productivity > 0.0) && !r.allows_productivity() {
+            return Err(format!("Productivity modules only go into machines making intermediates, not {}", recipe));
+        }
+        if modules.beacon.len() > BEACON_MODULE_SLOTS {
+            return Err(format!("A beacon only holds {} modules", BEACON_MODULE_SLOTS));
+        }
+        if modules.beacon.iter().any(|m| m.productivity > 0.0) {
+            return Err(String::from("Productivity modules can not go into beacons"));
+        }
+        if modules.beacons > MAX_BEACONS_PER_MACHINE {
+            return Err(format!("At most {} beacons can reach a machine in a row", MAX_BEACONS_PER_MACHINE));
+        }
+        self.modules.insert(recipe.to_string(), modules);
+        Ok(())
+    }
+
+    pub fn set_belt_tier(&mut self, belt_name: &str) -> Result<(), String> {
+        self.belt = belts::belt_tier(belt_name).ok_or(format!("Unknown belt {}", belt_name))?;
+        Ok(())
+    }
+
+    pub fn belt_tier(&self) -> &'static BeltTier {
+        self.belt
+    }
+
+    pub fn set_pole(&mut self, pole_name: &str) -> Result<(), String> {
+        self.pole = power::pole(pole_name).ok_or(format!("Unknown electric pole {}", pole_name))?;
+        Ok(())
+    }
+
+    pub fn pole(&self) -> &'static Pole {
+        self.pole
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+    }
+
+    pub fn region(&self) -> &Region {
+        &self.region
+    }
+
+    pub fn modules_for(&self, recipe: Recipe) -> &ModuleConfig {
+        self.modules.get(recipe.name()).unwrap_or(&NO_MODULES)
+    }
+
+    // Uses the chosen tier, or the first machine in the catalogue that can handle the category.
+    pub fn machine_for(&self, category: &str) -> Option<&'static Machine> {
+        self.tiers.get(category).copied().or_else(|| MACHINES.iter().find(|m| m.supports(category)))
+    }
+
+    pub fn machine_for_recipe(&self, recipe: Recipe) -> Option<&'static Machine> {
+        self.machine_for(recipe.category())
+    }
+
+    // Crafting speed after modules and beacons, which can slow a machine down to 20% at most.
+    pub fn crafting_speed(&self, recipe: Recipe) -> Option<f64> {
+        let machine = self.machine_for_recipe(recipe)?;
+        Some(machine.crafting_speed * (1.0 + self.modules_for(recipe).speed_bonus()).max(0.2))
+    }
+
+    // Factor on everything a craft yields.
+    pub fn productivity(&self, recipe: Recipe) -> f64 {
+        1.0 + self.modules_for(recipe).productivity_bonus()
+    }
+
+    pub fn machine_for_building(&self, building: &Building) -> Option<&'static Machine> {
+        match building {
+            Building::Assembler(recipe) | Building::ChemicalLab(recipe) | Building::Refinery(recipe) => self.machine_for_recipe(Recipe::by_name(recipe)?),
+            Building::Furnace => self.machine_for("smelting"),
+            Building::Miner => self.machine_for("basic-solid"),
+            _ => None,
+        }
+    }
+}
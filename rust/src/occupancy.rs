@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+
+use crate::bounds::Bounds;
+
+// Side of a map chunk in tiles.
+pub const CHUNK_SIZE: isize = 32;
+
+type Rows = [u32; CHUNK_SIZE as usize];
+
+// A set of tiles, stored as a bitmap per chunk with one word for every row of the chunk.
+// Chunks without any tiles are dropped, so two sets with the same tiles compare equal.
+#[derive(Clone, Default, Debug, PartialEq, Eq)]
+pub struct Occupancy {
+    chunks: HashMap<(isize, isize), Rows>,
+    len: usize,
+}
+
+// The chunk of a tile, its row in the chunk and its bit in that row.
+fn locate(x: isize, y: isize) -> ((isize, isize), usize, u32) {
+    ((x.div_euclid(CHUNK_SIZE), y.div_euclid(CHUNK_SIZE)), y.rem_euclid(CHUNK_SIZE) as usize, 1 << x.rem_euclid(CHUNK_SIZE))
+}
+
+// Every chunk row a rectangle touches, with the bits of its columns in that row.
+fn spans(b: Bounds) -> impl Iterator<Item = ((isize, isize), usize, u32)> {
+    let chunk_columns = if b.min_x < b.max_x { b.min_x.div_euclid(CHUNK_SIZE)..(b.max_x - 1).div_euclid(CHUNK_SIZE) + 1 } else { 0..0 };
+    (b.min_y..b.max_y).flat_map(move |y| chunk_columns.clone().map(move |cx| {
+        let from = b.min_x.max(cx * CHUNK_SIZE) - cx * CHUNK_SIZE;
+        let to = b.max_x.min((cx + 1) * CHUNK_SIZE) - cx * CHUNK_SIZE;
+        let below_to = if to == CHUNK_SIZE { u32::MAX } else { (1 << to) - 1 };
+        ((cx, y.div_euclid(CHUNK_SIZE)), y.rem_euclid(CHUNK_SIZE) as usize, below_to & !((1 << from) - 1))
+    }))
+}
+
+impl Occupancy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn contains(&self, x: isize, y: isize) -> bool {
+        let (chunk, row, bit) = locate(x, y);
+        self.chunks.get(&chunk).is_some_and(|rows| rows[row] & bit != 0)
+    }
+
+    // Whether the tile was free before.
+    pub fn insert(&mut self, x: isize, y: isize) -> bool {
+        let (chunk, row, bit) = locate(x, y);
+        let rows = self.chunks.entry(chunk).or_insert([0; CHUNK_SIZE as usize]);
+        let added = rows[row] & bit == 0;
+        rows[row] |= bit;
+        self.len += added as usize;
+        added
+    }
+
+    // Whether the tile was taken before.
+    pub fn remove(&mut self, x: isize, y: isize) -> bool {
+        let (chunk, row, bit) = locate(x, y);
+        let Some(rows) = self.chunks.get_mut(&chunk) else {
+            return false;
+        };
+        let removed = rows[row] & bit != 0;
+        rows[row] &= !bit;
+        if rows.iter().all(|r| *r == 0) {
+            self.chunks.remove(&chunk);
+        }
+        self.len -= removed as usize;
+        removed
+    }
+
+    // Takes every tile of the rectangle, the max edges are exclusive like everywhere else.
+    pub fn insert_rect(&mut self, b: Bounds) {
+        for (chunk, row, bits) in spans(b) {
+            let rows = self.chunks.entry(chunk).or_insert([0; CHUNK_SIZE as usize]);
+            self.len += (bits & !rows[row]).count_ones() as usize;
+            rows[row] |= bits;
+        }
+    }
+
+    // Whether any tile of the rectangle is taken.
+    pub fn any_in(&self, b: Bounds) -> bool {
+        spans(b).any(|(chunk, row, bits)| self.chunks.get(&chunk).is_some_and(|rows| rows[row] & bits != 0))
+    }
+
+    // How many tiles of the rectangle are taken.
+    pub fn count_in(&self, b: Bounds) -> usize {
+        spans(b).map(|(chunk, row, bits)| self.chunks.get(&chunk).map_or(0, |rows| (rows[row] & bits).count_ones() as usize)).sum()
+    }
+
+    pub fn extend(&mut self, other: &Occupancy) {
+        for (chunk, other_rows) in &other.chunks {
+            let rows = self.chunks.entry(*chunk).or_insert([0; CHUNK_SIZE as usize]);
+            for (row, other_row) in rows.iter_mut().zip(other_rows) {
+                self.len += (other_row & !*row).count_ones() as usize;
+                *row |= other_row;
+            }
+        }
+    }
+
+    pub fn tiles(&self) -> impl Iterator<Item = (isize, isize)> + '_ {
+        self.chunks.iter().flat_map(|((cx, cy), rows)| rows.iter().enumerate().flat_map(move |(row, bits)| {
+            (0..CHUNK_SIZE).filter(move |column| bits & (1 << column) != 0).map(move |column| (cx * CHUNK_SIZE + column, cy * CHUNK_SIZE + row as isize))
+        }))
+    }
+
+    // Moves whole chunks when the offset is a multiple of the chunk size, every tile otherwise.
+    pub fn move_rel(&self, x: isize, y: isize) -> Self {
+        if x % CHUNK_SIZE == 0 && y % CHUNK_SIZE == 0 {
+            let chunks = self.chunks.iter().map(|((cx, cy), rows)| ((cx + x / CHUNK_SIZE, cy + y / CHUNK_SIZE), *rows)).collect();
+            return Occupancy { chunks, len: self.len };
+        }
+        self.map(|tx, ty| (tx + x, ty + y))
+    }
+
+    pub fn map(&self, f: impl Fn(isize, isize) -> (isize, isize)) -> Self {
+        self.tiles().map(|(x, y)| f(x, y)).collect()
+    }
+}
+
+impl FromIterator<(isize, isize)> for Occupancy {
+    fn from_iter<I: IntoIterator<Item = (isize, isize)>>(tiles: I) -> Self {
+        let mut occupancy = Occupancy::new();
+        for (x, y) in tiles {
+            occupancy.insert(x, y);
+        }
+        occupancy
+    }
+}
@@ -0,0 +1,179 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{astar::Region, belts::{self, BeltTier}, machines::{Machine, MachineConfig, ModuleConfig}, recipes::{Recipe, State}};
+
+// An ingredient flowing into a step. The producer can make it as a byproduct, then the item is not its main product.
+#[derive(Clone, Debug)]
+pub struct Input {
+    pub item: String,
+    pub state: State,
+    pub producer: Recipe,
+    pub amount_per_sec: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct Step {
+    pub recipe: Recipe,
+    pub machine: &'static Machine,
+    pub modules: ModuleConfig,
+    // Crafting speed and productivity factor with modules and beacons applied.
+    pub speed: f64,
+    pub productivity: f64,
+    pub amount_per_sec: f64,
+    pub machines: f64,
+    pub machine_count: usize,
+    // Cheapest belt that carries the output, or the fastest allowed one when it takes several.
+    pub belt: &'static BeltTier,
+    pub belts: usize,
+    // Rate of every ingredient flowing into this step, in the order of the recipe.
+    pub inputs: Vec<Input>,
+    // Rate at which every consumer takes the output of this step, in the order of the steps.
+    pub consumers: Vec<(Recipe, f64)>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Plan {
+    pub target: Recipe,
+    pub amount_per_sec: f64,
+    pub belt: &'static BeltTier,
+    // Where the layout may go, in blueprint coordinates.
+    pub region: Region,
+    steps: Vec<Step>,
+    index: HashMap<Recipe, usize>,
+}
+
+impl Plan {
+    /// Works out the machines every step needs to make `amount_per_sec` of the target.
+    pub fn new(target: Recipe, amount_per_sec: f64, config: &MachineConfig) -> Result<Self, String> {
+        let mut order = Vec::new();
+        visit(target, &mut HashSet::new(), &mut HashSet::new(), &mut order)?;
+        // Every consumer has to be totalled before its producers, so walk from the target down.
+        order.reverse();
+
+        let mut plan = Plan {
+            target,
+            amount_per_sec,
+            belt: config.belt_tier(),
+            region: config.region().clone(),
+            steps: Vec::new(),
+            index: HashMap::new(),
+        };
+
+        for recipe in order {
+            let machine = config.machine_for_recipe(recipe).ok_or(format!("No machine can craft {:?} ({})", recipe, recipe.category()))?;
+            let modules = config.modules_for(recipe).clone();
+            validate_modules(recipe, machine, &modules)?;
+
+            plan.index.insert(recipe, plan.steps.len());
+            plan.steps.push(Step {
+                recipe,
+                machine,
+                modules,
+                speed: config.crafting_speed(recipe).unwrap(),
+                productivity: config.productivity(recipe),
+                amount_per_sec: 0.0,
+                machines: 0.0,
+                machine_count: 0,
+                belt: config.belt_tier(),
+                belts: 0,
+                inputs: Vec::new(),
+                consumers: Vec::new(),
+            });
+        }
+        plan.steps[0].amount_per_sec = amount_per_sec;
+
+        for i in 0..plan.steps.len() {
+            let recipe = plan.steps[i].recipe;
+            // Productivity gives part of the output for free, so less input is needed.
+            let crafts = plan.steps[i].amount_per_sec / plan.steps[i].productivity;
+            for (ingredient, dependency, a) in recipe.inputs() {
+                let flow = a * crafts;
+                // A byproduct comes with more of the main product, the producer is sized by that.
+                let made = flow / dependency.yield_of(&ingredient.name);
+                let producer = &mut plan.steps[plan.index[&dependency]];
+                producer.amount_per_sec += made;
+                match producer.consumers.iter_mut().find(|(r, _)| *r == recipe) {
+                    Some((_, total)) => *total += made,
+                    None => producer.consumers.push((recipe, made)),
+                }
+                plan.steps[i].inputs.push(Input { item: ingredient.name.clone(), state: ingredient.state, producer: dependency, amount_per_sec: flow });
+            }
+        }
+
+        for step in &mut plan.steps {
+            step.machines = step.amount_per_sec * step.recipe.crafting_time() / (step.speed * step.productivity);
+            step.machine_count = count(step.machines);
+            if step.recipe.output_state().is_solid() {
+                step.belt = belts::cheapest_belt(step.amount_per_sec, plan.belt).unwrap_or(plan.belt);
+                step.belts = count(step.amount_per_sec / step.belt.throughput());
+            }
+        }
+
+        Ok(plan)
+    }
+
+    // Steps ordered so that every consumer comes before the steps producing its ingredients.
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    pub fn step(&self, recipe: Recipe) -> Option<&Step> {
+        self.index.get(&recipe).map(|i| &self.steps[*i])
+    }
+
+    pub fn raw_inputs(&self) -> Vec<(Recipe, f64)> {
+        self.steps.iter().filter(|s| s.inputs.is_empty()).map(|s| (s.recipe, s.amount_per_sec)).collect()
+    }
+
+    pub fn flow(&self, producer: Recipe, consumer: Recipe) -> f64 {
+        self.step(consumer).map_or(0.0, |s| s.inputs.iter().filter(|i| i.producer == producer).map(|i| i.amount_per_sec).sum())
+    }
+}
+
+fn validate_modules(recipe: Recipe, machine: &Machine, modules: &ModuleConfig) -> Result<(), String> {
+    if modules.is_empty() {
+        return Ok(());
+    }
+    if machine.module_slots == 0 {
+        return Err(format!("{} can not be affected by modules", machine.name));
+    }
+    if modules.machine.len() > machine.module_slots {
+        return Err(format!("{} only has {} module slots", machine.name, machine.module_slots));
+    }
+    // The beacon row goes above the machines, where rows with fluid inputs have their pipes.
+    if modules.beacons > 0 && (machine.size != (3, 3) || recipe.ingredients().iter().any(|i| i.state.is_liquid())) {
+        return Err(format!("Beacons are only placed around 3x3 machines without fluid inputs, not for {:?}", recipe));
+    }
+    Ok(())
+}
+
+// Depth first post order, so every recipe ends up after all of its dependencies.
+fn visit(recipe: Recipe, visiting: &mut HashSet<Recipe>, done: &mut HashSet<Recipe>, order: &mut Vec<Recipe>) -> Result<(), String> {
+    if done.contains(&recipe) {
+        return Ok(());
+    }
+    if !visiting.insert(recipe) {
+        return Err(format!("Recipe cycle through {:?}", recipe));
+    }
+
+    for (dependency, _) in recipe.dependencies() {
+        visit(dependency, visiting, done, order)?;
+    }
+
+    visiting.remove(&recipe);
+    done.insert(recipe);
+    order.push(recipe);
+    Ok(())
+}
+
+// Rounds up, but without turning float noise like 2.0000000001 into an extra machine. The noise is relative, so even
+// the smallest amount still takes one.
+pub fn count(amount: f64) -> usize {
+    (amount * (1.0 - 1e-9)).ceil().max(0.0) as usize
+}
+
+// What is left of `amount` once `taken` is used, float noise left over is nothing.
+pub fn leftover(amount: f64, taken: f64) -> f64 {
+    let left = amount - taken;
+    if left <= amount.abs() * 1e-9 { 0.0 } else { left }
+}
@@ -34,47 +34,41 @@ impl Position {
         }
     }
 
-    pub fn rotate_left(&self) -> Self {
+    // Faces `rotation` further, see `Rotation::compose`.
+    pub fn turned(&self, rotation: Rotation) -> Self {
         Position {
             x: self.x,
             y: self.y,
-            rotation: match self.rotation {
-                Rotation::North => Rotation::West,
-                Rotation::East => Rotation::North,
-                Rotation::South => Rotation::East,
-                Rotation::West => Rotation::South
-            }
+            rotation: self.rotation.compose(rotation),
         }
     }
 
-    pub fn rotate_right(&self) -> Self {
-        Position {
-            x: self.x,
-            y: self.y,
-            rotation: match self.rotation {
-                Rotation::North => Rotation::East,
-                Rotation::East => Rotation::South,
-                Rotation::South => Rotation::West,
-                Rotation::West => Rotation::North
-            }
-        }
+    pub fn forward(&self) -> Self {
+        let (x, y) = self.rotation.offset();
+        self.move_rel(x, y)
     }
 
-    pub fn forward(&self) -> Self {
-        match self.rotation {
-            Rotation::North => self.move_rel(0, -1),
-            Rotation::East => self.move_rel(1, 0),
-            Rotation::South => self.move_rel(0, 1),
-            Rotation::West => self.move_rel(-1, 0)
+    pub fn backward(&self) -> Self {
+        let (x, y) = self.rotation.offset();
+        self.move_rel(-x, -y)
+    }
+
+    // Turns the tile around the pivot tile, and what it faces with it.
+    pub fn rotate_around(&self, (px, py): (isize, isize), rotation: Rotation) -> Self {
+        let (x, y) = rotation.turn((self.x - px, self.y - py));
+        Position {
+            x: px + x,
+            y: py + y,
+            rotation: self.rotation.compose(rotation.cardinal()),
         }
     }
 
-    pub fn backward(&self) -> Self {
-        match self.rotation {
-            Rotation::North => self.move_rel(0, 1),
-            Rotation::East => self.move_rel(-1, 0),
-            Rotation::South => self.move_rel(0, -1),
-            Rotation::West => self.move_rel(1, 0)
+    // Mirrors the tile from left to right over the column `px`, and what it faces with it.
+    pub fn mirror_around(&self, px: isize) -> Self {
+        Position {
+            x: 2 * px - self.x,
+            y: self.y,
+            rotation: self.rotation.mirror(),
         }
     }
-}
\ No newline at end of file
+}
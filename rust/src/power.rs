@@ -0,0 +1,243 @@
+use std::collections::HashSet;
+
+use serde_json::Value;
+
+use crate::{building::Building, entity::Entity, position::Position, rotation::Rotation};
+
+#[derive(Debug, PartialEq)]
+pub struct Pole {
+    pub name: &'static str,
+    pub size: isize,
+    // Half the side of the square around the pole that gets power.
+    pub supply_radius: f64,
+    pub wire_reach: f64,
+}
+
+pub const POLES: &[Pole] = &[
+    Pole { name: "small-electric-pole", size: 1, supply_radius: 2.5, wire_reach: 7.5 },
+    Pole { name: "medium-electric-pole", size: 1, supply_radius: 3.5, wire_reach: 9.0 },
+    Pole { name: "big-electric-pole", size: 2, supply_radius: 2.0, wire_reach: 30.0 },
+    Pole { name: "substation", size: 2, supply_radius: 9.0, wire_reach: 18.0 },
+];
+
+pub fn pole(name: &str) -> Option<&'static Pole> {
+    POLES.iter().find(|p| p.name == name)
+}
+
+// Entities that look like they need power, but burn fuel instead.
+const BURNERS: &[&str] = &["burner-inserter", "burner-mining-drill", "stone-furnace", "steel-furnace"];
+
+fn needs_power(entity: &Entity) -> bool {
+    let powered = matches!(entity.building, Building::Assembler(_) | Building::Furnace | Building::ChemicalLab(_) | Building::Refinery(_)
+        | Building::Miner | Building::Inserter | Building::LongInserter | Building::Beacon);
+    powered && !BURNERS.contains(&entity.name.as_str())
+}
+
+// Pole placed in the network, with its centre in tile units.
+struct Placed {
+    index: usize,
+    pole: &'static Pole,
+    x: f64,
+    y: f64,
+}
+
+impl Placed {
+    fn new(index: usize, entity: &Entity, pole: &'static Pole) -> Self {
+        let b = entity.bounds();
+        Placed { index, pole, x: (b.min_x + b.max_x) as f64 / 2.0, y: (b.min_y + b.max_y) as f64 / 2.0 }
+    }
+
+    fn supplies(&self, entity: &Entity) -> bool {
+        let b = entity.bounds();
+        let r = self.pole.supply_radius;
+        (b.min_x as f64) < self.x + r && (b.max_x as f64) > self.x - r && (b.min_y as f64) < self.y + r && (b.max_y as f64) > self.y - r
+    }
+
+    fn reaches(&self, other: &Placed) -> bool {
+        let distance = ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt();
+        distance <= self.pole.wire_reach.min(other.pole.wire_reach) + 1e-9
+    }
+
+    fn distance_to(&self, x: f64, y: f64) -> f64 {
+        ((self.x - x).powi(2) + (self.y - y).powi(2)).sqrt()
+    }
+}
+
+// Adds poles of the given type until every entity that needs power is supplied and all poles form one network,
+// then writes the wires into the `neighbours` lists. Entities are renumbered from 1 as blueprints expect.
+// Poles that are already in the list are kept and become part of the network.
+pub fn electrify(entities: &mut Vec<Entity>, pole: &'static Pole) -> Result<(), String> {
+    for (i, entity) in entities.iter_mut().enumerate() {
+        entity.entity_number = i + 1;
+    }
+
+    let mut taken = HashSet::new();
+    for entity in entities.iter() {
+        taken.extend(entity.bounds().tiles());
+    }
+
+    let mut poles: Vec<Placed> = entities.iter().enumerate().filter_map(|(i, e)| self::pole(&e.name).map(|p| Placed::new(i, e, p))).collect();
+    let mut uncovered: Vec<usize> = (0..entities.len()).filter(|i| needs_power(&entities[*i]) && !poles.iter().any(|p| p.supplies(&entities[*i]))).collect();
+
+    while let Some(target) = uncovered.first().copied() {
+        let placed = best_supplying_pole(entities, &uncovered, target, &poles, &taken, pole)
+            .ok_or(format!("No room for a pole next to {} at {:?}", entities[target].name, entities[target].position))?;
+        let placed = add_pole(entities, &mut taken, pole, placed);
+        uncovered.retain(|i| !placed.supplies(&entities[*i]));
+        poles.push(placed);
+    }
+
+    connect(entities, &mut poles, &mut taken, pole)?;
+    wire(entities, &poles);
+    Ok(())
+}
+
+fn pole_entity(pole: &'static Pole, x: isize, y: isize) -> Entity {
+    let mut entity = Entity::from(Building::Pole);
+    entity.name = pole.name.to_string();
+    entity.set_position(Position { x, y, rotation: Rotation::North });
+    entity
+}
+
+fn fits(taken: &HashSet<(isize, isize)>, entity: &Entity) -> bool {
+    entity.bounds().tiles().all(|tile| !taken.contains(&tile))
+}
+
+fn add_pole(entities: &mut Vec<Entity>, taken: &mut HashSet<(isize, isize)>, pole: &'static Pole, mut entity: Entity) -> Placed {
+    taken.extend(entity.bounds().tiles());
+    entity.entity_number = entities.len() + 1;
+    entities.push(entity);
+    Placed::new(entities.len() - 1, &entities[entities.len() - 1], pole)
+}
+
+// Tries every free spot from which a pole reaches the target and picks the one supplying the most uncovered entities,
+// preferring spots within wire reach of the existing network.
+fn best_supplying_pole(entities: &[Entity], uncovered: &[usize], target: usize, poles: &[Placed], taken: &HashSet<(isize, isize)>, pole: &'static Pole) -> Option<Entity> {
+    let b = entities[target].bounds();
+    let range = pole.supply_radius.ceil() as isize + pole.size;
+    let nearby: Vec<usize> = uncovered.iter().copied().filter(|i| entities[*i].bounds().intersects(b.expand(2 * range + 1))).collect();
+
+    let mut best: Option<((usize, bool, isize), Entity)> = None;
+    for x in b.min_x - range..=b.max_x + range {
+        for y in b.min_y - range..=b.max_y + range {
+            let candidate = pole_entity(pole, x, y);
+            if !fits(taken, &candidate) {
+                continue;
+            }
+            let placed = Placed::new(0, &candidate, pole);
+            if !placed.supplies(&entities[target]) {
+                continue;
+            }
+
+            let covered = nearby.iter().filter(|i| placed.supplies(&entities[**i])).count();
+            let connected = poles.iter().any(|p| p.reaches(&placed));
+            let spread = -(x - entities[target].position.x).abs() - (y - entities[target].position.y).abs();
+            let score = (covered, connected, spread);
+            if best.as_ref().map(|(s, _)| score > *s).unwrap_or(true) {
+                best = Some((score, candidate));
+            }
+        }
+    }
+    best.map(|(_, entity)| entity)
+}
+
+fn components(poles: &[Placed]) -> Vec<usize> {
+    let mut component: Vec<usize> = (0..poles.len()).collect();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for a in 0..poles.len() {
+            for b in 0..poles.len() {
+                if component[b] < component[a] && poles[a].reaches(&poles[b]) {
+                    component[a] = component[b];
+                    changed = true;
+                }
+            }
+        }
+    }
+    component
+}
+
+// Bridges separate networks with extra poles, always between the two closest poles that are not connected yet.
+fn connect(entities: &mut Vec<Entity>, poles: &mut Vec<Placed>, taken: &mut HashSet<(isize, isize)>, pole: &'static Pole) -> Result<(), String> {
+    loop {
+        let component = components(poles);
+        let mut closest: Option<(f64, usize, usize)> = None;
+        for a in 0..poles.len() {
+            for b in 0..poles.len() {
+                if component[a] == 0 && component[b] != 0 {
+                    let distance = poles[a].distance_to(poles[b].x, poles[b].y);
+                    if closest.map(|(d, _, _)| distance < d).unwrap_or(true) {
+                        closest = Some((distance, a, b));
+                    }
+                }
+            }
+        }
+        let Some((_, a, b)) = closest else {
+            return Ok(());
+        };
+
+        // Step from pole a towards pole b as far as the wire allows.
+        let reach = pole.wire_reach.min(poles[a].pole.wire_reach);
+        let mut best: Option<(f64, Entity)> = None;
+        let (from_x, from_y) = (poles[a].x, poles[a].y);
+        let steps = reach.ceil() as isize;
+        for dx in -steps..=steps {
+            for dy in -steps..=steps {
+                let candidate = pole_entity(pole, from_x.round() as isize + dx, from_y.round() as isize + dy);
+                if !fits(taken, &candidate) {
+                    continue;
+                }
+                let placed = Placed::new(0, &candidate, pole);
+                if !placed.reaches(&poles[a]) {
+                    continue;
+                }
+                let distance = placed.distance_to(poles[b].x, poles[b].y);
+                if best.as_ref().map(|(d, _)| distance < *d).unwrap_or(true) {
+                    best = Some((distance, candidate));
+                }
+            }
+        }
+
+        let (distance, candidate) = best.ok_or(format!("No room for a pole to connect {} at {:?}", entities[poles[a].index].name, entities[poles[a].index].position))?;
+        if distance >= poles[a].distance_to(poles[b].x, poles[b].y) {
+            return Err(format!("Can not get closer to {} at {:?} with poles", entities[poles[b].index].name, entities[poles[b].index].position));
+        }
+        let placed = add_pole(entities, taken, pole, candidate);
+        poles.push(placed);
+    }
+}
+
+// Wires a spanning tree, which keeps every pole well below the five connections the game allows.
+fn wire(entities: &mut [Entity], poles: &[Placed]) {
+    let mut edges = Vec::new();
+    for a in 0..poles.len() {
+        for b in a + 1..poles.len() {
+            if poles[a].reaches(&poles[b]) {
+                edges.push((poles[a].distance_to(poles[b].x, poles[b].y), a, b));
+            }
+        }
+    }
+    edges.sort_by(|x, y| x.0.partial_cmp(&y.0).unwrap());
+
+    let mut component: Vec<usize> = (0..poles.len()).collect();
+    let mut neighbours = vec![Vec::new(); poles.len()];
+    for (_, a, b) in edges {
+        let (ca, cb) = (component[a], component[b]);
+        if ca == cb {
+            continue;
+        }
+        for c in &mut component {
+            if *c == cb {
+                *c = ca;
+            }
+        }
+        neighbours[a].push(entities[poles[b].index].entity_number);
+        neighbours[b].push(entities[poles[a].index].entity_number);
+    }
+
+    for (placed, wires) in poles.iter().zip(neighbours) {
+        let wires = wires.into_iter().map(Value::from).collect();
+        entities[placed.index].extra.insert(String::from("neighbours"), Value::Array(wires));
+    }
+}
@@ -1,32 +1,15 @@
-use std::{collections::HashMap, hash::Hash};
+use std::{collections::HashMap, fmt, fs, str::FromStr, sync::OnceLock};
+
+use serde_json::Value;
 
 use crate::building::Building;
 
-#[derive(Eq, PartialEq, Hash, Clone, Copy, Debug)]
-pub enum Recipe {
-    IronOre,
-    CopperOre,
-    Stone,
-    Coal,
-    IronPlate,
-    CopperPlate,
-    FastInserter,
-    Inserter,
-    ElectronicCircuit,
-    SmallElectricMotor,
-    BurnerInserter,
-    IronStick,
-    SingleCylinderEngine,
-    IronGearWheel,
-    CopperCable,
-    StoneTablet,
-    StoneBrick,
-    BigElectricMotor,
-    Lubricant,
-    SteelPlate,
-}
+// Trimmed recipe dump that is used when no other database has been installed.
+const DEFAULT_RECIPES: &str = include_str!("../data/recipes.json");
 
-#[derive(Eq, PartialEq, Hash, Clone, Copy)]
+static DATABASE: OnceLock<RecipeDb> = OnceLock::new();
+
+#[derive(Eq, PartialEq, Hash, Clone, Copy, Debug)]
 pub enum State {
     Solid,
     Liquid,
@@ -34,113 +17,366 @@ pub enum State {
 
 impl State {
     pub fn is_solid(&self) -> bool {
-        return *self == State::Solid
+        *self == State::Solid
     }
 
     pub fn is_liquid(&self) -> bool {
-        return *self == State::Liquid
+        *self == State::Liquid
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Ingredient {
+    pub name: String,
+    pub amount: f64,
+    pub state: State,
+}
+
+#[derive(Clone, Debug)]
+pub struct Product {
+    pub name: String,
+    pub amount: f64,
+    pub probability: f64,
+    pub state: State,
+}
+
+impl Product {
+    pub fn expected_amount(&self) -> f64 {
+        self.amount * self.probability
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RecipeData {
+    pub name: String,
+    pub category: String,
+    pub energy: f64,
+    pub ingredients: Vec<Ingredient>,
+    pub results: Vec<Product>,
+    pub main_product: usize,
+    pub resource: bool,
+    // Whether productivity modules may go into the machines crafting it, which the game only allows for intermediates.
+    pub productivity: bool,
+}
+
+impl RecipeData {
+    pub fn main_product(&self) -> &Product {
+        &self.results[self.main_product]
+    }
+
+    // Expected amount of an item a single craft adds, ingredients that come back out (catalysts) are subtracted.
+    pub fn net_output(&self, item: &str) -> f64 {
+        let produced: f64 = self.results.iter().filter(|p| p.name == item).map(Product::expected_amount).sum();
+        let consumed: f64 = self.ingredients.iter().filter(|i| i.name == item).map(|i| i.amount).sum();
+        produced - consumed
+    }
+}
+
+pub struct RecipeDb {
+    recipes: Vec<RecipeData>,
+    by_name: HashMap<String, usize>,
+    // Item name to the recipe used to make it, with the preference it was registered with.
+    producers: HashMap<String, (usize, usize)>,
+}
+
+impl RecipeDb {
+    /// Reads a recipe database from a json file, see [`RecipeDb::from_json`].
+    pub fn load(path: &str) -> Result<Self, String> {
+        let json = fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+        Self::from_json(&json)
+    }
+
+    /// Accepts both the full `--dump-data` output and a trimmed file with only the `recipe`, `resource`, `fluid` and
+    /// `module` tables. Recipes take productivity modules when they are in the `limitation` of a productivity module
+    /// or have `allow_productivity` set, so without either no recipe takes them.
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let root: Value = serde_json::from_str(json).map_err(|e| format!("Invalid recipe json: {}", e))?;
+        let intermediates: Vec<&str> = root.get("module").and_then(Value::as_object).into_iter().flatten()
+            .filter(|(_, module)| module.pointer("/effect/productivity").is_some())
+            .flat_map(|(_, module)| module.get("limitation").and_then(Value::as_array).map(Vec::as_slice).unwrap_or_default())
+            .filter_map(Value::as_str)
+            .collect();
+        let fluids: Vec<&str> = root.get("fluid").and_then(Value::as_object).map(|f| f.keys().map(String::as_str).collect()).unwrap_or_default();
+        let state_of = |kind: Option<&str>, name: &str| {
+            if kind == Some("fluid") || fluids.contains(&name) { State::Liquid } else { State::Solid }
+        };
+
+        let mut db = RecipeDb {
+            recipes: Vec::new(),
+            by_name: HashMap::new(),
+            producers: HashMap::new(),
+        };
+
+        let recipes = root.get("recipe").and_then(Value::as_object).ok_or("Recipe json has no recipe table")?;
+        for (name, recipe) in recipes {
+            // Prefer normal mode when the recipe has difficulty variants.
+            let body = recipe.get("normal").filter(|n| n.is_object()).unwrap_or(recipe);
+
+            let mut ingredients = Vec::new();
+            for ingredient in body.get("ingredients").and_then(Value::as_array).map(Vec::as_slice).unwrap_or_default() {
+                let (kind, item, amount) = parse_stack(ingredient).ok_or(format!("Invalid ingredient in {}", name))?;
+                ingredients.push(Ingredient { name: item.to_string(), amount, state: state_of(kind, item) });
+            }
+
+            let results = parse_results(body, &state_of).ok_or(format!("Recipe {} has no results", name))?;
+            let main_product = match body.get("main_product").or(recipe.get("main_product")).and_then(Value::as_str).filter(|m| !m.is_empty()) {
+                Some(main) => results.iter().position(|p| p.name == main).ok_or(format!("Main product of {} is not one of its results", name))?,
+                None => results.iter().position(|p| p.name == *name).unwrap_or(0),
+            };
+
+            db.push(RecipeData {
+                name: name.clone(),
+                category: recipe.get("category").and_then(Value::as_str).unwrap_or("crafting").to_string(),
+                energy: body.get("energy_required").and_then(Value::as_f64).unwrap_or(0.5),
+                ingredients,
+                results,
+                main_product,
+                resource: false,
+                productivity: body.get("allow_productivity").or(recipe.get("allow_productivity")).and_then(Value::as_bool)
+                    .unwrap_or(intermediates.contains(&name.as_str())),
+            });
+        }
+
+        for (name, resource) in root.get("resource").and_then(Value::as_object).into_iter().flatten() {
+            let minable = resource.get("minable").ok_or(format!("Resource {} is not minable", name))?;
+            let results = parse_results(minable, &state_of).ok_or(format!("Resource {} has no results", name))?;
+            db.push(RecipeData {
+                name: name.clone(),
+                category: resource.get("category").and_then(Value::as_str).unwrap_or("basic-solid").to_string(),
+                energy: minable.get("mining_time").and_then(Value::as_f64).unwrap_or(1.0),
+                ingredients: Vec::new(),
+                results,
+                main_product: 0,
+                resource: true,
+                productivity: true,
+            });
+        }
+
+        // Everything that is consumed but never produced has to be brought in from outside, treat it as a resource.
+        let mut missing: Vec<Ingredient> = db.recipes.iter().flat_map(|r| r.ingredients.iter()).filter(|i| !db.producers.contains_key(&i.name)).cloned().collect();
+        missing.sort_by(|a, b| a.name.cmp(&b.name));
+        missing.dedup_by(|a, b| a.name == b.name);
+        for ingredient in missing {
+            db.push(RecipeData {
+                name: ingredient.name.clone(),
+                category: String::from(if ingredient.state.is_liquid() { "basic-fluid" } else { "basic-solid" }),
+                energy: 1.0,
+                ingredients: Vec::new(),
+                results: vec![Product { name: ingredient.name, amount: 1.0, probability: 1.0, state: ingredient.state }],
+                main_product: 0,
+                resource: true,
+                productivity: true,
+            });
+        }
+
+        Ok(db)
+    }
+
+    fn push(&mut self, recipe: RecipeData) {
+        let index = self.recipes.len();
+        for (i, product) in recipe.results.iter().enumerate() {
+            if recipe.net_output(&product.name) <= 0.0 {
+                continue;
+            }
+
+            // Recipes named after their product win over recipes with it as main product, which win over byproducts.
+            // This picks "solid-fuel" over "solid-fuel-from-light-oil" and "light-oil-cracking" over "advanced-oil-processing".
+            let preference = if recipe.name == product.name { 2 } else if i == recipe.main_product { 1 } else { 0 };
+            match self.producers.get(&product.name) {
+                Some((_, p)) if *p >= preference => {}
+                _ => { self.producers.insert(product.name.clone(), (index, preference)); }
+            }
+        }
+        self.by_name.insert(recipe.name.clone(), index);
+        self.recipes.push(recipe);
+    }
+
+    pub fn get(&self, name: &str) -> Option<Recipe> {
+        self.by_name.get(name).map(|i| Recipe(*i))
+    }
+
+    pub fn producer(&self, item: &str) -> Option<Recipe> {
+        self.producers.get(item).map(|(i, _)| Recipe(*i))
+    }
+
+    pub fn recipes(&self) -> impl Iterator<Item = Recipe> {
+        (0..self.recipes.len()).map(Recipe)
+    }
+}
+
+fn parse_stack(stack: &Value) -> Option<(Option<&str>, &str, f64)> {
+    match stack {
+        Value::Array(pair) => Some((None, pair.first()?.as_str()?, pair.get(1)?.as_f64()?)),
+        Value::Object(_) => {
+            // Results can give a range instead of a fixed amount, use its average.
+            let amount = match stack.get("amount").and_then(Value::as_f64) {
+                Some(amount) => amount,
+                None => (stack.get("amount_min")?.as_f64()? + stack.get("amount_max")?.as_f64()?) / 2.0,
+            };
+            Some((stack.get("type").and_then(Value::as_str), stack.get("name")?.as_str()?, amount))
+        }
+        _ => None,
+    }
+}
+
+fn parse_results(body: &Value, state_of: &dyn Fn(Option<&str>, &str) -> State) -> Option<Vec<Product>> {
+    if let Some(result) = body.get("result").and_then(Value::as_str) {
+        let amount = body.get("result_count").and_then(Value::as_f64).unwrap_or(1.0);
+        return Some(vec![Product { name: result.to_string(), amount, probability: 1.0, state: state_of(None, result) }]);
+    }
+
+    let mut results = Vec::new();
+    for result in body.get("results")?.as_array()? {
+        let (kind, name, amount) = parse_stack(result)?;
+        let probability = result.get("probability").and_then(Value::as_f64).unwrap_or(1.0);
+        results.push(Product { name: name.to_string(), amount, probability, state: state_of(kind, name) });
+    }
+    if results.is_empty() { None } else { Some(results) }
+}
+
+/// Installs a recipe database, only possible before the first recipe lookup.
+pub fn install(db: RecipeDb) -> Result<(), String> {
+    DATABASE.set(db).map_err(|_| String::from("A recipe database is already in use"))
+}
+
+pub fn database() -> &'static RecipeDb {
+    DATABASE.get_or_init(|| RecipeDb::from_json(DEFAULT_RECIPES).unwrap())
+}
+
+#[derive(Eq, PartialEq, Hash, Clone, Copy)]
+pub struct Recipe(usize);
+
+impl fmt::Debug for Recipe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Recipe {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Recipe::by_name(name).ok_or(format!("Unknown recipe {}", name))
     }
 }
 
 impl Recipe {
-    pub fn dependencies(&self) -> HashMap<Recipe, f64> {
-        match self {
-            Self::IronOre => HashMap::new(),
-            Self::CopperOre => HashMap::new(),
-            Self::Stone => HashMap::new(),
-            Self::Coal => HashMap::new(),
-            Self::IronPlate => HashMap::from([(Recipe::IronOre, 1.0)]),
-            Self::CopperPlate => HashMap::from([(Recipe::CopperOre, 1.0)]),
-            Self::FastInserter => HashMap::from([(Recipe::ElectronicCircuit, 2.0), (Recipe::Inserter, 1.0), (Recipe::IronPlate, 2.0)]),
-            Self::Inserter => HashMap::from([(Recipe::SmallElectricMotor, 1.0), (Recipe::BurnerInserter, 1.0)]),
-            Self::ElectronicCircuit => HashMap::from([(Recipe::CopperCable, 3.0), (Recipe::StoneTablet, 1.0)]),
-            Self::SmallElectricMotor => HashMap::from([(Recipe::IronGearWheel, 1.0), (Recipe::CopperCable, 6.0), (Recipe::IronPlate, 1.0)]),
-            Self::BurnerInserter => HashMap::from([(Recipe::IronStick, 2.0), (Recipe::SingleCylinderEngine, 1.0)]),
-            Self::IronStick => HashMap::from([(Recipe::IronPlate, 0.5)]),
-            Self::SingleCylinderEngine => HashMap::from([(Recipe::IronGearWheel, 1.0), (Recipe::IronPlate, 1.0)]),
-            Self::IronGearWheel => HashMap::from([(Recipe::IronPlate, 2.0)]),
-            Self::CopperCable => HashMap::from([(Recipe::CopperPlate, 0.5)]),
-            Self::StoneTablet => HashMap::from([(Recipe::StoneBrick, 0.25)]),
-            Self::StoneBrick => HashMap::from([(Recipe::Stone, 2.0)]),
-            Self::BigElectricMotor => HashMap::from([(Recipe::Lubricant, 40.0), (Recipe::ElectronicCircuit, 4.0), (Recipe::SmallElectricMotor, 2.0), (Recipe::SteelPlate, 2.0)]),
-            Self::SteelPlate => HashMap::from([(Recipe::IronPlate, 5.0)]),
-            Self::Lubricant => HashMap::new(),
+    /// Looks up a recipe by its internal recipe name, like "advanced-oil-processing".
+    /// That is not always the name of what it makes, see [`Recipe::producer`] for that.
+    pub fn by_name(name: &str) -> Option<Recipe> {
+        database().get(name)
+    }
+
+    /// The recipe making an item, by the internal name of the item.
+    pub fn producer(item: &str) -> Option<Recipe> {
+        database().producer(item)
+    }
+
+    pub fn data(&self) -> &'static RecipeData {
+        &database().recipes[self.0]
+    }
+
+    pub fn name(&self) -> &'static str {
+        &self.data().name
+    }
+
+    pub fn category(&self) -> &'static str {
+        &self.data().category
+    }
+
+    pub fn energy(&self) -> f64 {
+        self.data().energy
+    }
+
+    pub fn allows_productivity(&self) -> bool {
+        self.data().productivity
+    }
+
+    pub fn ingredients(&self) -> &'static [Ingredient] {
+        &self.data().ingredients
+    }
+
+    pub fn results(&self) -> &'static [Product] {
+        &self.data().results
+    }
+
+    pub fn main_product(&self) -> &'static Product {
+        self.data().main_product()
+    }
+
+    // Net amount of the main product a single craft yields.
+    pub fn output(&self) -> f64 {
+        self.data().net_output(&self.main_product().name)
+    }
+
+    // Every ingredient used up, with the recipe making it and the amount needed for a single unit of output.
+    pub fn inputs(&self) -> Vec<(&'static Ingredient, Recipe, f64)> {
+        let output = self.output();
+        let mut inputs: Vec<(&'static Ingredient, Recipe, f64)> = Vec::new();
+        for ingredient in self.ingredients() {
+            let consumed = -self.data().net_output(&ingredient.name);
+            if consumed > 0.0 && !inputs.iter().any(|(i, _, _)| i.name == ingredient.name) {
+                inputs.push((ingredient, Recipe::producer(&ingredient.name).unwrap(), consumed / output));
+            }
         }
+        inputs
     }
 
-    pub fn crafting_time(&self) -> f64 {
-        match self {
-            Self::IronOre => 1.0,
-            Self::CopperOre => 1.0,
-            Self::Stone => 1.0,
-            Self::Coal => 1.0,
-            Self::IronPlate => 3.2,
-            Self::CopperPlate => 3.2,
-            Self::FastInserter => 0.5,
-            Self::Inserter => 0.5,
-            Self::ElectronicCircuit => 0.5,
-            Self::SmallElectricMotor => 0.8,
-            Self::BurnerInserter => 0.5,
-            Self::IronStick => 0.25,
-            Self::SingleCylinderEngine => 0.6,
-            Self::IronGearWheel => 0.5,
-            Self::CopperCable => 0.25,
-            Self::StoneTablet => 0.125,
-            Self::StoneBrick => 3.2,
-            Self::BigElectricMotor => 10.0,
-            Self::SteelPlate => 16.0,
-            Self::Lubricant => 1.0,
+    // Amount of each dependency needed for a single unit of output, counted in the main product of the dependency,
+    // in the order of the ingredients.
+    pub fn dependencies(&self) -> Vec<(Recipe, f64)> {
+        let mut dependencies: Vec<(Recipe, f64)> = Vec::new();
+        for (ingredient, producer, amount) in self.inputs() {
+            let amount = amount / producer.yield_of(&ingredient.name);
+            match dependencies.iter_mut().find(|(r, _)| *r == producer) {
+                Some((_, total)) => *total += amount,
+                None => dependencies.push((producer, amount)),
+            }
+        }
+        dependencies
+    }
+
+    // Amount of an item made along with a single unit of the main product, 1 for the main product itself.
+    pub fn yield_of(&self, item: &str) -> f64 {
+        self.data().net_output(item) / self.output()
+    }
+
+    // Everything besides the main product that comes out, per unit of main product, in the order of the results.
+    pub fn byproducts(&self) -> Vec<(String, f64)> {
+        let output = self.output();
+        let mut byproducts = Vec::new();
+        for product in self.results() {
+            let produced = self.data().net_output(&product.name);
+            if product.name != self.main_product().name && produced > 0.0 && !byproducts.iter().any(|(item, _)| *item == product.name) {
+                byproducts.push((product.name.clone(), produced / output));
+            }
         }
+        byproducts
+    }
+
+    pub fn byproduct_state(&self, item: &str) -> State {
+        self.results().iter().find(|p| p.name == item).map(|p| p.state).unwrap_or(State::Solid)
+    }
+
+    // Crafting time for a single unit of output at crafting speed 1.
+    pub fn crafting_time(&self) -> f64 {
+        self.energy() / self.output()
     }
 
     pub fn building(&self) -> Building {
-        match self {
-            Self::IronOre => Building::Miner,
-            Self::CopperOre => Building::Miner,
-            Self::Stone => Building::Miner,
-            Self::Coal => Building::Miner,
-            Self::IronPlate => Building::Furnace,
-            Self::CopperPlate => Building::Furnace,
-            Self::FastInserter => Building::Assembler(String::from("fast-inserter")),
-            Self::Inserter => Building::Assembler(String::from("inserter")),
-            Self::ElectronicCircuit => Building::Assembler(String::from("electronic-circuit")),
-            Self::SmallElectricMotor => Building::Assembler(String::from("electric-motor")),
-            Self::BurnerInserter => Building::Assembler(String::from("burner-inserter")),
-            Self::IronStick => Building::Assembler(String::from("iron-stick")),
-            Self::SingleCylinderEngine => Building::Assembler(String::from("motor")),
-            Self::IronGearWheel => Building::Assembler(String::from("iron-gear-wheel")),
-            Self::CopperCable => Building::Assembler(String::from("copper-cable")),
-            Self::StoneTablet => Building::Assembler(String::from("stone-tablet")),
-            Self::StoneBrick => Building::Assembler(String::from("stone-brick")),
-            Self::BigElectricMotor => Building::Assembler(String::from("electric-engine-unit")),
-            Self::SteelPlate => Building::Furnace,
-            Self::Lubricant => Building::ChemicalLab,
+        if self.data().resource {
+            return Building::Miner;
+        }
+
+        match self.category() {
+            "smelting" => Building::Furnace,
+            "chemistry" => Building::ChemicalLab(self.name().to_string()),
+            "oil-processing" => Building::Refinery(self.name().to_string()),
+            _ => Building::Assembler(self.name().to_string()),
         }
     }
 
     pub fn output_state(&self) -> State {
-        match self {
-            Self::IronOre => State::Solid,
-            Self::CopperOre => State::Solid,
-            Self::Stone => State::Solid,
-            Self::Coal => State::Solid,
-            Self::IronPlate => State::Solid,
-            Self::CopperPlate => State::Solid,
-            Self::FastInserter => State::Solid,
-            Self::Inserter => State::Solid,
-            Self::ElectronicCircuit => State::Solid,
-            Self::SmallElectricMotor => State::Solid,
-            Self::BurnerInserter => State::Solid,
-            Self::IronStick => State::Solid,
-            Self::SingleCylinderEngine => State::Solid,
-            Self::IronGearWheel => State::Solid,
-            Self::CopperCable => State::Solid,
-            Self::StoneTablet => State::Solid,
-            Self::StoneBrick => State::Solid,
-            Self::BigElectricMotor => State::Solid,
-            Self::SteelPlate => State::Solid,
-            Self::Lubricant => State::Liquid,
-        }
+        self.main_product().state
     }
-    
-}
\ No newline at end of file
+}
@@ -1,19 +1,114 @@
-
+// The eight directions of the game, clockwise from north. Only belts and machines facing the four cardinal
+// directions are placed, the diagonal ones come from rails and other entities in imported blueprints.
 #[derive(PartialEq, PartialOrd, Eq, Hash, Clone, Copy, Debug)]
 pub enum Rotation {
     North,
+    NorthEast,
     East,
+    SouthEast,
     South,
+    SouthWest,
     West,
+    NorthWest,
 }
 
 impl Rotation {
+    pub const ALL: [Rotation; 8] = [
+        Self::North,
+        Self::NorthEast,
+        Self::East,
+        Self::SouthEast,
+        Self::South,
+        Self::SouthWest,
+        Self::West,
+        Self::NorthWest,
+    ];
+
+    pub const CARDINAL: [Rotation; 4] = [Self::North, Self::East, Self::South, Self::West];
+
     pub fn assemble(&self) -> usize {
         match self {
             Self::North => 0,
+            Self::NorthEast => 1,
             Self::East => 2,
+            Self::SouthEast => 3,
             Self::South => 4,
+            Self::SouthWest => 5,
             Self::West => 6,
+            Self::NorthWest => 7,
+        }
+    }
+
+    // Inverse of `assemble`.
+    pub fn from_direction(direction: usize) -> Self {
+        Self::ALL[direction % 8]
+    }
+
+    // Used as a turn, North turns nothing and East a quarter turn clockwise. Turning by `other` after `self`.
+    pub fn compose(&self, other: Rotation) -> Self {
+        Self::from_direction(self.assemble() + other.assemble())
+    }
+
+    // The turn that undoes this one.
+    pub fn inverse(&self) -> Self {
+        Self::from_direction(8 - self.assemble())
+    }
+
+    // Mirrored from left to right, which for a direction is the same as turning back.
+    pub fn mirror(&self) -> Self {
+        self.inverse()
+    }
+
+    pub fn left(&self) -> Self {
+        self.compose(Self::West)
+    }
+
+    pub fn right(&self) -> Self {
+        self.compose(Self::East)
+    }
+
+    pub fn is_diagonal(&self) -> bool {
+        self.assemble() % 2 == 1
+    }
+
+    // Diagonal directions are rounded down to the cardinal direction before them.
+    pub fn cardinal(&self) -> Self {
+        Self::from_direction(self.assemble() / 2 * 2)
+    }
+
+    // The step to the neighbouring tile in this direction.
+    pub fn offset(&self) -> (isize, isize) {
+        match self {
+            Self::North => (0, -1),
+            Self::NorthEast => (1, -1),
+            Self::East => (1, 0),
+            Self::SouthEast => (1, 1),
+            Self::South => (0, 1),
+            Self::SouthWest => (-1, 1),
+            Self::West => (-1, 0),
+            Self::NorthWest => (-1, -1),
         }
     }
-}
\ No newline at end of file
+
+    // Turns an offset given for something facing north so it matches this rotation, clockwise like the game does.
+    // Offsets on the tile grid only turn in quarters, diagonal rotations turn like their `cardinal` one.
+    pub fn turn(&self, (x, y): (isize, isize)) -> (isize, isize) {
+        match self.cardinal() {
+            Self::North => (x, y),
+            Self::East => (-y, x),
+            Self::South => (-x, -y),
+            _ => (y, -x),
+        }
+    }
+
+    // Side of the origin an offset lies on, diagonal offsets count as above or below.
+    pub fn side(x: isize, y: isize) -> Self {
+        if y.abs() >= x.abs() {
+            if y < 0 { Self::North } else { Self::South }
+        } else if x < 0 {
+            Self::West
+        } else {
+            Self::East
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use std::{collections::{HashMap, HashSet}, fmt};
+
+use crate::{astar::{Astar, Costs, NoPath, Region}, belts::BeltTier, occupancy::Occupancy, position::Position};
+
+// Rounds of ripping up and rerouting before the router gives up.
+const MAX_ITERATIONS: usize = 30;
+
+// A belt connection from the last belt of an output to the tile where the belt into an input has to go.
+#[derive(Clone, Debug)]
+pub struct Net {
+    pub start: Position,
+    pub end: Position,
+    pub item: String,
+    pub belt: &'static BeltTier,
+    // Tiles this net may not use on top of the blocked ones.
+    pub avoid: Occupancy,
+}
+
+impl Net {
+    pub fn new(start: Position, end: Position, item: &str, belt: &'static BeltTier) -> Self {
+        Net { start, end, item: item.to_string(), belt, avoid: Occupancy::new() }
+    }
+
+    pub fn move_rel(&self, x: isize, y: isize) -> Self {
+        Net { start: self.start.move_rel(x, y), end: self.end.move_rel(x, y), avoid: self.avoid.move_rel(x, y), ..self.clone() }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Failure {
+    NoPath(NoPath),
+    // Still shares tiles with other nets after the last round.
+    Congested,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Failure::NoPath(reason) => reason.fmt(f),
+            Failure::Congested => write!(f, "every path is taken by other nets"),
+        }
+    }
+}
+
+// Tiles taken by a path as the belt A* returns it, which runs from the end back to the tile after the start.
+// A gap between two steps is an underground pair, which takes the tile of its entrance as well.
+pub fn path_tiles(start: Position, path: &[Position]) -> Vec<Position> {
+    let mut tiles = Vec::new();
+    let mut previous = start;
+    for p in path.iter().rev() {
+        if (p.x - previous.x).abs() + (p.y - previous.y).abs() > 1 {
+            let entrance = previous.forward();
+            tiles.push(Position::north(entrance.x, entrance.y));
+        }
+        tiles.push(Position::north(p.x, p.y));
+        previous = *p;
+    }
+    tiles
+}
+
+/// Routes all nets together in the style of PathFinder. Every round each net is ripped up and routed again, paying for
+/// tiles other nets use right now and for tiles that were fought over in earlier rounds. Both costs grow until every
+/// net has tiles of its own. Fails with the indices of the nets that have no path or still share tiles.
+pub fn route(blocked: &Occupancy, region: &Region, nets: &[Net], costs: Costs) -> Result<Vec<Vec<Position>>, Vec<(usize, Failure)>> {
+    let mut paths: Vec<Option<Vec<Position>>> = vec![None; nets.len()];
+    let mut history: HashMap<Position, isize> = HashMap::new();
+    let mut present = 1;
+    let mut failed = Vec::new();
+
+    // Every net keeps clear of where the others start and end.
+    let endpoints: Vec<[(isize, isize); 2]> = nets.iter().map(|net| {
+        let front = net.start.forward();
+        [(front.x, front.y), (net.end.x, net.end.y)]
+    }).collect();
+
+    for _ in 0..MAX_ITERATIONS {
+        for i in 0..nets.len() {
+            // The search does not know the tiles it already used, a path running over its own tiles pays for them
+            // like for the tiles of another net.
+            let crossed = paths[i].take().map(|path| crossings(nets[i].start, &path)).unwrap_or_default();
+            let occupancy = occupancy(nets, &paths);
+
+            let mut penalties = history.clone();
+            for (tile, users) in occupancy.into_iter().chain(crossed.into_iter().map(|tile| (tile, 1))) {
+                let history = history.get(&tile).copied().unwrap_or(0);
+                *penalties.entry(tile).or_insert(0) += present * users as isize * (1 + history);
+            }
+
+            let mut evade = nets[i].avoid.clone();
+            for (j, tiles) in endpoints.iter().enumerate() {
+                if j != i {
+                    for (x, y) in tiles {
+                        evade.insert(*x, *y);
+                    }
+                }
+            }
+
+            let net = &nets[i];
+            let mut astar = Astar::new(net.start, net.end, blocked);
+            astar.set_evade(evade);
+            astar.set_underground(net.belt.underground_reach);
+            astar.set_costs(costs);
+            astar.set_region(region.clone());
+            astar.set_penalties(penalties);
+            match astar.astar_belt() {
+                Ok(path) => paths[i] = Some(path),
+                Err(reason) => failed.push((i, Failure::NoPath(reason))),
+            }
+        }
+
+        // Penalties never open up blocked tiles, so a net without a path stays without one.
+        if !failed.is_empty() {
+            return Err(failed);
+        }
+        let shared: HashSet<Position> = occupancy(nets, &paths).into_iter().filter(|(_, users)| *users > 1).map(|(tile, _)| tile).collect();
+        if shared.is_empty() {
+            return Ok(paths.into_iter().flatten().collect());
+        }
+        for tile in shared {
+            *history.entry(tile).or_insert(0) += 1;
+        }
+        present *= 2;
+    }
+
+    let shared: HashSet<Position> = occupancy(nets, &paths).into_iter().filter(|(_, users)| *users > 1).map(|(tile, _)| tile).collect();
+    Err((0..nets.len()).filter(|i| {
+        paths[*i].as_ref().is_some_and(|path| path_tiles(nets[*i].start, path).iter().any(|t| shared.contains(t)))
+    }).map(|i| (i, Failure::Congested)).collect())
+}
+
+// Tiles a path takes more than once.
+fn crossings(start: Position, path: &[Position]) -> Vec<Position> {
+    let mut seen = HashSet::new();
+    path_tiles(start, path).into_iter().filter(|tile| !seen.insert(*tile)).collect()
+}
+
+// How many nets use every tile.
+fn occupancy(nets: &[Net], paths: &[Option<Vec<Position>>]) -> HashMap<Position, usize> {
+    let mut occupancy = HashMap::new();
+    for (net, path) in nets.iter().zip(paths) {
+        if let Some(path) = path {
+            for tile in path_tiles(net.start, path) {
+                *occupancy.entry(tile).or_insert(0) += 1;
+            }
+        }
+    }
+    occupancy
+}
@@ -1,8 +1,8 @@
-use std::{collections::{HashMap, BTreeMap}, hash::Hash};
+use std::collections::HashMap;
 
-use rand::Rng;
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
-use crate::{recipes::Recipe, position::Position, building::Building, rotation::Rotation, grid::Grid, entity::Entity};
+use crate::{recipes::Recipe, planner::Plan, machines::MachineConfig, building::Building, rotation::Rotation, grid::Grid, entity::Entity, error::SynthesisError};
 
 
 
@@ -39,19 +39,23 @@ struct Node {
 
 pub struct SpringSystem {
     nodes: Vec<Node>,
-    dependencies: BTreeMap<usize, (usize, f64)>,
+    // (child, parent, force), a machine of a shared intermediate can feed several parents.
+    dependencies: Vec<(usize, usize, f64)>,
     recipe: Recipe,
     velocities: Vec<FloatVec>,
+    machines: MachineConfig,
 }
 
 impl SpringSystem {
-    pub fn new(recipe: Recipe, item_per_sec: f64) -> Self {
+    /// The machines start out at random positions, the same seed gives the same layout.
+    pub fn new(plan: &Plan, machines: MachineConfig, seed: u64) -> Result<Self, SynthesisError> {
 
         let mut system = Self {
             nodes: Vec::new(),
-            dependencies: BTreeMap::new(),
-            recipe,
+            dependencies: Vec::new(),
+            recipe: plan.target,
             velocities: Vec::new(),
+            machines,
         };
 
         let center_node = Node {
@@ -63,50 +67,60 @@ impl SpringSystem {
         system.velocities.push(FloatVec { x: 0.0, y: 0.0 });
         
 
-        system.generate(recipe, item_per_sec, Some(0), 1.0);
+        system.generate(plan, seed);
         for count in 0..1000 {
-            system.cycle(count);
+            system.cycle(count)?;
         }
-        system
+        Ok(system)
     }
 
-    fn generate(&mut self, recipe: Recipe, item_per_sec: f64, parent: Option<usize>, layer: f64) {
-        if recipe.building() == Building::Miner {
-            return;
-        }
-
-        println!("Creating {:?} amount: {}", recipe, item_per_sec);
-
-        let mut rng = rand::thread_rng();
-        let float_devices_needed = recipe.crafting_time() * item_per_sec;
+    fn generate(&mut self, plan: &Plan, seed: u64) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut machines: HashMap<Recipe, Vec<usize>> = HashMap::new();
+        let mut layers: HashMap<Recipe, f64> = HashMap::from([(plan.target, 1.0)]);
 
-        let devices_needed = float_devices_needed.ceil() as usize;
-        let parts_per_device = float_devices_needed / float_devices_needed.ceil();
-        for _ in 0..devices_needed {
-            let node = Node {
-                pos: FloatVec { x: rng.gen(), y: rng.gen() },
-                building: recipe.building()
-            };
-            println!("Initial positions: {}, {:?}", self.nodes.len(), node.pos);
+        // Steps come consumers first, so every parent already has its machines when its producers are created.
+        for step in plan.steps() {
+            if step.recipe.building() == Building::Miner {
+                continue;
+            }
 
-            self.nodes.push(node);
-            self.velocities.push(FloatVec { x: 0.0, y: 0.0 });
-            let node_index = self.nodes.len() - 1;
+            let layer = layers[&step.recipe];
+            let mut indices = Vec::new();
+            for _ in 0..step.machine_count {
+                let node = Node {
+                    pos: FloatVec { x: rng.gen(), y: rng.gen() },
+                    building: step.recipe.building()
+                };
+
+                self.nodes.push(node);
+                self.velocities.push(FloatVec { x: 0.0, y: 0.0 });
+                indices.push(self.nodes.len() - 1);
+            }
 
-            println!("Parent: {:?}", parent);
-            if let Some(p) = parent {
-                self.dependencies.insert(node_index, (p, 1.0 / layer));
+            // Spread the machines over the machines of every consumer instead of duplicating them per consumer.
+            let mut parents: Vec<usize> = step.consumers.iter().flat_map(|(c, _)| machines.get(c).cloned().unwrap_or_default()).collect();
+            if step.recipe == plan.target {
+                parents.push(0);
+            }
+            // Round robin, so every machine has a parent and every parent has at least one supplier.
+            for k in 0..indices.len().max(parents.len()) {
+                if indices.is_empty() || parents.is_empty() {
+                    break;
+                }
+                self.dependencies.push((indices[k % indices.len()], parents[k % parents.len()], 1.0 / layer));
             }
 
-            // generate children for node.
-            for (c, amount) in recipe.dependencies() {
-                println!("Some index: {:?}", Some(node_index));
-                self.generate(c, parts_per_device * amount, Some(node_index), layer * 2.0);
+            for input in &step.inputs {
+                let l = layers.entry(input.producer).or_insert(layer * 2.0);
+                *l = l.max(layer * 2.0);
             }
+            machines.insert(step.recipe, indices);
         }
     }
 
-    fn cycle(&mut self, count: usize) {
+    // Fails when the forces blow up and the machines fly apart.
+    fn cycle(&mut self, _count: usize) -> Result<(), SynthesisError> {
         let mut forces: HashMap<usize, FloatVec> = HashMap::new();
         for index in 0..self.nodes.len() {
             forces.insert(index, FloatVec { x: 0.0, y: 0.0 });
@@ -117,7 +131,7 @@ impl SpringSystem {
             for (i2, n2) in self.nodes.iter().enumerate() {
                 if i1 == i2 { continue;}
                 if n1.pos.x > 1e20 || n2.pos.x > 1e20 {
-                    panic!("Numbers getting too large")
+                    return Err(SynthesisError::NoPlacement(String::from("the spring system does not settle, machines drift too far apart")));
                 }
                 
                 let diff = n1.pos.sub(&n2.pos);
@@ -129,52 +143,56 @@ impl SpringSystem {
 
 
                 let force = FloatVec { x: diff.x * (repulsion / dist), y: diff.y * (repulsion / dist) };
-                // println!("Repulsion between {i1}: {:?} and {i2}: {:?} is {:?}", n1.pos, n2.pos, force);
                 
                 let previous = forces.remove(&i1).unwrap();
                 forces.insert(i1, previous.add(&force));
             }
         }
 
-        for (i1, (i2, force)) in &self.dependencies {
+        for (i1, i2, force) in &self.dependencies {
             let n1 = &self.nodes[*i1];
             let n2 = &self.nodes[*i2];
             let mut diff = n2.pos.sub(&n1.pos).mul(0.1);
-            let mut dist = diff.len() * force;
+            let dist = diff.len() * force;
 
             diff = diff.mul(dist * dist * dist);
-            let prev1 = forces.remove(&i1).unwrap();
-            let prev2 = forces.remove(&i2).unwrap();
+            let prev1 = forces.remove(i1).unwrap();
+            let prev2 = forces.remove(i2).unwrap();
             
 
             forces.insert(*i1, prev1.add(&diff));
             forces.insert(*i2, prev2.sub(&diff));
-
-            // println!("Attaction between : {i1}, {i2}, forces: {:?}, n1.x: {}, n2.x: {}", diff, n1.pos.x, n2.pos.x);
         }
 
-        println!("Velocities: {:?}", self.velocities);
         // Apply forces to nodes.
         for (index, node) in self.nodes.iter_mut().enumerate() {
             let force = forces.remove(&index).unwrap();
-            // println!("Node: {index}, forces: {:?} position: {:?}", force, node.pos);
 
             self.velocities[index] = self.velocities[index].add(&force.mul(0.01)).mul(0.99);
             node.pos = node.pos.add(&self.velocities[index]);
         }
+        Ok(())
     }
 
-    pub fn partial_blueprint(&self) {
+    /// A blueprint string of the machines only, without belts or inserters.
+    pub fn partial_blueprint(&self) -> String {
         let mut grid = Grid::new(self.recipe);
         for node in &self.nodes {
-            println!("Dependencies: {:?}", self.dependencies);
-            println!("Node position: {:?}", node.pos);
-            let mut entity = Entity::from(node.building.clone());
+            let mut entity = match self.machines.machine_for_building(&node.building) {
+                Some(machine) => Entity::machine(node.building.clone(), machine),
+                None => Entity::from(node.building.clone()),
+            };
+            if let Building::Assembler(recipe) | Building::ChemicalLab(recipe) | Building::Refinery(recipe) = &node.building {
+                // The nodes are built from the plan, so their recipes are known.
+                if let Some(recipe) = Recipe::by_name(recipe) {
+                    entity.items = self.machines.modules_for(recipe).machine_items();
+                }
+            }
             entity.position.x = (node.pos.x) as isize;
             entity.position.y = (node.pos.y) as isize;
             entity.direction = Rotation::North.assemble();
             grid.add_entity(entity);
         }
-        println!("Partial blueprint: {}", grid.to_blueprint());
+        grid.to_blueprint()
     }
 }
\ No newline at end of file
@@ -1,108 +1,399 @@
-use crate::{recipes::{Recipe, State}, grid::Grid, rotation::Rotation, building::Building, entity::Entity, position::Position};
+use std::collections::HashSet;
 
+use serde_json::Value;
 
+use crate::{recipes::{Recipe, State}, grid::{Grid, Byproduct}, rotation::Rotation, building::Building, entity::Entity, position::Position, planner::{self, Plan, Step}, belts::{self, BeltTier, Lane}, inserters::{self, InserterTier}, astar::Region, error::SynthesisError};
 
 
-pub fn synth(recipe: Recipe, amount_per_sec: f64) -> Grid {
-    // synth this new step
 
-    let mut row = synth_row(recipe, (amount_per_sec * recipe.crafting_time()).ceil() as isize);
+/// Lays a plan out as rows of machines, one row per step, with their belts and pipes routed.
+pub fn synth(plan: &Plan) -> Result<Grid, SynthesisError> {
+    let mut grid = synth_step(plan, plan.target, plan.amount_per_sec, &mut HashSet::new(), &plan.region)?;
+    if let Some(demand) = grid.demands().first() {
+        return Err(SynthesisError::InvalidInput(format!("Nothing in the plan feeds {} into {:?}", demand.item, demand.position)));
+    }
+    // All belts are routed together once every grid is placed, so no net takes the only way another one has.
+    grid.route_nets()?;
+    // Grids are only placed inside the region, this catches the rows that do not fit into it at all.
+    let outside = grid.collect_entities().into_iter().find(|e| !e.bounds().tiles().all(|(x, y)| plan.region.contains(Position::north(x, y))));
+    if let Some(entity) = outside {
+        return Err(SynthesisError::NoPlacement(format!("{} at ({}, {}) is outside the build area", entity.name, entity.position.x, entity.position.y)));
+    }
+    Ok(grid)
+}
 
+// `built` holds the intermediate steps that were built already, by the consumer that needed them first. Only the grid
+// of the target knows where it ends up, the others get `region` from the grid they are placed in.
+fn synth_step(plan: &Plan, recipe: Recipe, amount_per_sec: f64, built: &mut HashSet<Recipe>, region: &Region) -> Result<Grid, SynthesisError> {
+    let step = plan.step(recipe).ok_or_else(|| SynthesisError::InvalidInput(format!("{} is not part of the plan", recipe.name())))?;
+    let share = amount_per_sec / step.amount_per_sec;
 
-    for (index, (r, a)) in recipe.dependencies().drain().enumerate() {
-        println!("Synthesizing {:?} for {:?}", r, recipe);
-        let child_grid = synth(r, a * amount_per_sec);
-        row.add(child_grid, index);
-    }
+    // Inserters drop everything on the far lane of the output belt, and every solid ingredient gets a lane of its own.
+    // When one lane of the fastest belt can not keep up, the step is split over several rows.
+    let output = if recipe.output_state().is_solid() {
+        amount_per_sec * (1.0 + recipe.byproducts().iter().filter(|(item, _)| recipe.byproduct_state(item).is_solid()).map(|(_, a)| a).sum::<f64>())
+    } else {
+        0.0
+    };
+    let input = step.inputs.iter().filter(|i| i.state.is_solid()).map(|i| i.amount_per_sec * share).fold(0.0, f64::max);
+    let rows = planner::count(output.max(input) / plan.belt.lane_throughput).max(1);
+
+    // The rows share one output belt as long as it can carry all of it.
+    let merged = output <= plan.belt.lane_throughput + 1e-9;
+    let lane = if merged { output.max(input / rows as f64) } else { output.max(input) / rows as f64 };
+    let belt = belts::cheapest_lane(lane, plan.belt).unwrap_or(plan.belt);
 
-    row
+    let rows_amount = amount_per_sec / rows as f64;
+    let mut grid = synth_part(plan, step, rows_amount, belt, built, region)?;
+    for _ in 1..rows {
+        let part = synth_part(plan, step, rows_amount, belt, built, &Region::Everywhere)?;
+        if merged {
+            grid.add_feeder(part)?;
+        } else {
+            grid.add_parallel(part)?;
+        }
+    }
+    grid.feed_demands()?;
+    Ok(grid)
 }
 
-pub fn synth_row(recipe: Recipe, row_length: isize) -> Grid {
+fn synth_part(plan: &Plan, step: &Step, amount_per_sec: f64, belt: &'static BeltTier, built: &mut HashSet<Recipe>, region: &Region) -> Result<Grid, SynthesisError> {
+    let share = amount_per_sec / step.amount_per_sec;
+    let mut row = synth_row(step, amount_per_sec, belt)?;
+    // The first row of the target is the only one not placed by a parent, it goes into the corner of the build area.
+    if let Region::Rect(area) = region {
+        row.move_into(*area);
+    }
+    row.set_region(region.clone());
+
+    for (index, input) in step.inputs.iter().enumerate() {
+        let needed = input.amount_per_sec * share;
+        let producer = plan.step(input.producer).ok_or_else(|| SynthesisError::InvalidInput(format!("{} is not part of the plan", input.producer.name())))?;
+        // Every intermediate step is built once, for all of its consumers, by the first one that needs it. The others
+        // are fed from what that one leaves over, wherever it ends up. Raw resources come in next to every consumer.
+        let raw = producer.inputs.is_empty();
+        if !raw && !built.insert(input.producer) {
+            row.add_demand(index, &input.item, input.state, needed)?;
+            continue;
+        }
+
+        let yields = input.producer.yield_of(&input.item);
+        let made = if raw { needed / yields } else { producer.amount_per_sec };
+        let mut child_grid = synth_step(plan, input.producer, made, built, &Region::Everywhere)?;
+        // The producer makes the ingredient as a byproduct, so that leaves through the output and its main product does not.
+        if input.item != input.producer.main_product().name {
+            child_grid.output_byproduct(&input.item)?;
+        }
+        row.add(child_grid, index, planner::leftover(made * yields, needed))?;
+    }
+    // Byproducts of the rows built so far can feed the inputs of other rows. Those nobody takes stay open ends of the grid.
+    row.feed_demands()?;
 
-    
+    Ok(row)
+}
 
+/// A row of machines making `amount_per_sec` of the step's product, with its belts and pipes but nothing connected to them.
+/// Byproducts leave on belts or pipes of their own, which are recorded as byproducts of the grid.
+pub fn synth_row(step: &Step, amount_per_sec: f64, belt: &'static BeltTier) -> Result<Grid, SynthesisError> {
+    let (recipe, machine) = (step.recipe, step.machine);
+    let mut row_length = planner::count(step.machines * amount_per_sec / step.amount_per_sec) as isize;
 
     let mut grid = Grid::new(recipe);
-    let (building_width, building_height) = recipe.building().size();
+    grid.set_belt(belt);
+    let (building_width, building_height) = machine.size;
+    // The building starts right below the output inserters.
+    let (building_x, building_y) = (machine.origin().0, 2 + machine.origin().1);
 
     if recipe.building() == Building::Miner {
-        grid.place_belts(0, 0, Rotation::West, row_length * building_width);
+        if recipe.output_state().is_liquid() {
+            // Fluids from outside the blueprint come in through a single pipe.
+            grid.place_pipe(0, 0, Rotation::North, 1, 1, &recipe.main_product().name);
+            grid.set_output(Position { x: 0, y: 0, rotation: Rotation::West });
+            grid.set_feed(Position { x: 1, y: 0, rotation: Rotation::West });
+            return Ok(grid);
+        }
+        let width = row_length * building_width;
+        grid.place_belts(0, 0, Rotation::West, width);
+        grid.set_lane(0, 0, width, Lane::Left, &recipe.main_product().name);
+        grid.set_lane(0, 0, width, Lane::Right, &recipe.main_product().name);
         grid.set_output(Position { x: 0, y: 0, rotation: Rotation::West});
-        return grid;
+        grid.set_feed(Position { x: width, y: 0, rotation: Rotation::West });
+        return Ok(grid);
+    }
+
+    let byproducts = recipe.byproducts();
+    if byproducts.iter().any(|(item, _)| recipe.byproduct_state(item) != recipe.output_state()) {
+        return Err(SynthesisError::unsupported(recipe.name(), "rows can not have both solid and fluid products"));
+    }
+    // Where every byproduct leaves the row.
+    let mut exits = Vec::new();
+
+    // Fluid ingredients go into the fluidboxes in recipe order.
+    let fluids: Vec<&str> = recipe.ingredients().iter()
+        .filter(|i| i.state.is_liquid() && step.inputs.iter().any(|input| input.item == i.name))
+        .map(|i| i.name.as_str())
+        .collect();
+    if fluids.len() > machine.fluid_inputs.len() {
+        return Err(SynthesisError::unsupported(recipe.name(), format!("{} has no room for {} fluids", machine.name, fluids.len())));
     }
+    // Pipes come in from above and leave below the machines, so the machines are turned to put their fluidboxes there.
+    // Without any fluids the fluidboxes are closed and the direction does not matter.
+    let rotation = if fluids.is_empty() && recipe.output_state().is_solid() {
+        Rotation::North
+    } else {
+        machine.orientation(Rotation::North, Rotation::South)
+            .ok_or_else(|| SynthesisError::unsupported(recipe.name(), format!("pipes can not reach the fluidboxes of {}", machine.name)))?
+    };
+    let (fluid_inputs, fluid_outputs) = machine.fluid_connections(rotation);
+
+    let solid_count = step.inputs.iter().filter(|i| i.state.is_solid()).count();
+    let mut building_entity = Entity::machine(recipe.building(), machine);
+    building_entity.items = step.modules.machine_items();
+    let solids: Vec<&str> = step.inputs.iter().filter(|i| i.state.is_solid()).map(|i| i.item.as_str()).collect();
 
+    // Fluid inputs take slots above the machines, fluid products the ones below.
+    let connection_slots: Vec<isize> = fluid_inputs.iter().take(fluids.len()).map(|(dx, _)| building_x + dx).collect();
+    let mut slots = inserter_slots(building_width);
+    slots.retain(|s| !connection_slots.contains(s));
+    let mut bottom_slots = inserter_slots(building_width);
     if recipe.output_state().is_liquid() {
-        grid.set_output(Position {x: 0, y: 0, rotation: Rotation::North});
-        return grid;
+        let products = byproducts.len() + 1;
+        let outputs = if products == 1 { fluid_outputs.len() } else { products };
+        let output_slots: Vec<isize> = fluid_outputs.iter().take(outputs).map(|(dx, _)| building_x + dx).collect();
+        bottom_slots.retain(|s| !output_slots.contains(s));
     }
 
-    let input_count = recipe.dependencies().len() as isize;
-    let fluid_input_count = recipe.dependencies().iter().filter(|x| {x.0.output_state().is_liquid()}).count() as isize;
-    let building_entity = Entity::from(recipe.building());
+    // Inserters only have to keep up with what every machine makes, which is less than it could when the row has
+    // machines to spare. When even the fastest ones can not keep up with that, the work is spread over more machines.
+    let needed_length = row_length;
+    let inserters = loop {
+        let inserters = Inserters::per_machine(step, amount_per_sec / row_length as f64)?;
+        if inserters.output.1 <= slots.len() && inserters.near.1 + inserters.far.1 <= bottom_slots.len() {
+            break inserters;
+        }
+        if row_length >= MAX_SPREAD * needed_length {
+            return Err(SynthesisError::unsupported(recipe.name(), format!("inserters can not keep up with {} per second", amount_per_sec)));
+        }
+        row_length += 1;
+    };
+    let width = row_length * building_width;
 
-    assert!(fluid_input_count < 2);
+    // Every fluid gets a pipe running above the row, with pipe-to-ground pairs going down to the fluidboxes.
+    let mut fluid_ends = Vec::new();
+    for (k, fluid) in fluids.iter().enumerate() {
+        let pipe_y = -2 - 2 * k as isize;
+        let slot = connection_slots[k];
+        grid.place_pipe_tunnel(building_y + fluid_inputs[k].1, slot, Rotation::South, row_length, building_width, fluid);
+        grid.place_pipe_tunnel(pipe_y + 1, slot, Rotation::North, row_length, building_width, fluid);
+        grid.place_pipe(pipe_y, 0, Rotation::North, width, 1, fluid);
+        fluid_ends.push((fluid.to_string(), Position { x: width, y: pipe_y, rotation: Rotation::East }));
+    }
 
-    // Assemble output row:
+    let mut belt_inputs = Vec::new();
     match recipe.output_state() {
         State::Solid => {
-            grid.place_belts(0, 0, Rotation::West, row_length * building_width);
-            grid.set_output(Position { x: 0, y: 0, rotation: Rotation::West });
+            let main = &recipe.main_product().name;
+            let dropped = Lane::dropped(Rotation::West, Rotation::North).unwrap();
+            grid.place_belts(0, 0, Rotation::West, width);
+            grid.set_lane(0, 0, width, dropped, main);
+            grid.set_feed(Position { x: width, y: 0, rotation: Rotation::West });
+            match &byproducts[..] {
+                [] => grid.set_output(Position { x: 0, y: 0, rotation: Rotation::West }),
+                // Inserters take every product out of the machines, so a splitter at the end of the output belt
+                // filters the byproduct out to its left, the row below, onto a belt of its own.
+                [(item, _)] => {
+                    let mut splitter = Entity::splitter(belt);
+                    splitter.extra.insert(String::from("filter"), Value::from(item.as_str()));
+                    splitter.extra.insert(String::from("output_priority"), Value::from("left"));
+                    grid.place_entity(splitter, 1, -1, Rotation::West, 1, 0);
+                    grid.place_belt(-2, 0, Rotation::West);
+                    grid.place_belt(-2, 1, Rotation::West);
+                    grid.set_lane(0, -2, 1, dropped, main);
+                    grid.set_lane(1, -2, 1, dropped, item);
+                    grid.set_output(Position { x: -2, y: 0, rotation: Rotation::West });
+                    exits.push((item.clone(), Position { x: -2, y: 1, rotation: Rotation::West }));
+                }
+                _ => return Err(SynthesisError::unsupported(recipe.name(), format!("rows filter at most one solid byproduct off their output, not {}", byproducts.len()))),
+            }
+
+            let (tier, count) = inserters.output;
+            place_inserter_slots(&mut grid, Entity::inserter(tier), 1, Rotation::North, &slots[..count], row_length, building_width);
+            grid.place_entity(building_entity, building_y, building_x, rotation, row_length, building_width);
+            place_beacons(&mut grid, step, row_length);
+
+            // Every solid ingredient gets a lane of its own. They are side-loaded onto the start of the input belts,
+            // which turns that first belt into a T-junction.
+            if solid_count <= 2 {
+                grid.place_belts(3 + building_height, -1, Rotation::East, width + 1);
+                let near = Position { x: -1, y: 3 + building_height, rotation: Rotation::East };
+                for (item, lane) in solids.iter().zip([Lane::Right, Lane::Left]) {
+                    grid.set_lane(near.y, near.x, width + 1, lane, item);
+                    belt_inputs.push(belts::feeder(near, lane));
+                }
+                place_input_inserters(&mut grid, &inserters, 2 + building_height, row_length, building_width);
+
+            } else if solid_count <= 4 {
+                // The first two ingredients arrive on the far belt, the others come down on the near belt from above.
+                grid.place_belts(4 + building_height, -3, Rotation::East, width + 3);
+                let far = Position { x: -3, y: 4 + building_height, rotation: Rotation::East };
+                grid.place_belts(3 + building_height, -2, Rotation::East, width + 2);
+                grid.place_belt(-2, 2 + building_height, Rotation::South);
+                grid.place_belt(-2, 1 + building_height, Rotation::South);
+                let near = Position { x: -2, y: 1 + building_height, rotation: Rotation::South };
+
+                for (item, lane) in solids.iter().take(2).zip([Lane::Right, Lane::Left]) {
+                    grid.set_lane(far.y, far.x, width + 3, lane, item);
+                    belt_inputs.push(belts::feeder(far, lane));
+                }
+                // Turning onto the near belt keeps the lanes.
+                for (item, lane) in solids.iter().skip(2).zip([Lane::Right, Lane::Left]) {
+                    grid.set_lane(near.y, near.x, 1, lane, item);
+                    grid.set_lane(near.y + 1, near.x, 1, lane, item);
+                    grid.set_lane(3 + building_height, -2, width + 2, lane, item);
+                    belt_inputs.push(belts::feeder(near, lane));
+                }
+                place_input_inserters(&mut grid, &inserters, 2 + building_height, row_length, building_width);
+            } else {
+                return Err(SynthesisError::unsupported(recipe.name(), format!("rows take at most 4 solid ingredients, not {}", solid_count)));
+            }
         }
 
         State::Liquid => {
-            todo!()
+            // Every fluid product leaves through a pipe of its own along the bottom, two tiles apart so they do not mix.
+            // A single product takes every output connection, several take one each in the order of the results.
+            grid.place_entity(building_entity, building_y, building_x, rotation, row_length, building_width);
+            let mut products: Vec<&str> = byproducts.iter().map(|(item, _)| item.as_str()).collect();
+            products.push(&recipe.main_product().name);
+            products.sort_by_key(|name| recipe.results().iter().position(|p| p.name == *name));
+            if products.len() > 1 && products.len() > fluid_outputs.len() {
+                return Err(SynthesisError::unsupported(recipe.name(), format!("{} has no room for {} fluid products", machine.name, products.len())));
+            }
+            if solid_count > 2 {
+                return Err(SynthesisError::unsupported(recipe.name(), format!("rows with a fluid product take at most 2 solid ingredients, not {}", solid_count)));
+            }
+            let connection_y = building_y + fluid_outputs[0].1;
+            let first_y = if solid_count > 0 { connection_y + 3 } else if products.len() > 1 { connection_y + 2 } else { connection_y };
+            let line_y = |k: usize| first_y + 2 * k as isize;
+
+            for (k, (dx, _)) in fluid_outputs.iter().enumerate() {
+                let product = if products.len() == 1 { 0 } else { k };
+                if product >= products.len() {
+                    continue;
+                }
+                // Pipe-to-ground pairs take the product under the input belt and the pipes of the other products.
+                if line_y(product) > connection_y {
+                    let slot = building_x + dx;
+                    grid.place_pipe_tunnel(connection_y, slot, Rotation::North, row_length, building_width, products[product]);
+                    grid.place_pipe_tunnel(line_y(product) - 1, slot, Rotation::South, row_length, building_width, products[product]);
+                }
+            }
+
+            if solid_count > 0 {
+                let (tier, count) = inserters.near;
+                place_inserter_slots(&mut grid, Entity::inserter(tier), connection_y, Rotation::North, &bottom_slots[..count], row_length, building_width);
+                grid.place_belts(connection_y + 1, -1, Rotation::East, width + 1);
+                let near = Position { x: -1, y: connection_y + 1, rotation: Rotation::East };
+                for (item, lane) in solids.iter().zip([Lane::Right, Lane::Left]) {
+                    grid.set_lane(near.y, near.x, width + 1, lane, item);
+                    belt_inputs.push(belts::feeder(near, lane));
+                }
+            }
+
+            for (k, product) in products.iter().enumerate() {
+                grid.place_pipe(line_y(k), 0, Rotation::North, width, 1, product);
+                let end = Position { x: 0, y: line_y(k), rotation: Rotation::West };
+                if *product == recipe.main_product().name {
+                    grid.set_output(end);
+                    grid.set_feed(Position { x: width, y: line_y(k), rotation: Rotation::West });
+                } else {
+                    exits.push((product.to_string(), end));
+                }
+            }
+        }
+    }
+
+    // Inputs are numbered like the ingredients in the plan.
+    let mut belt_inputs = belt_inputs.into_iter();
+    for input in &step.inputs {
+        match fluid_ends.iter().find(|(fluid, _)| *fluid == input.item) {
+            Some((fluid, end)) => grid.add_fluid_input(*end, fluid),
+            None => grid.add_input(belt_inputs.next().unwrap()),
         }
     }
 
-    // Place building.
-    if input_count > 5 || (fluid_input_count > 0 && input_count > 3) { // We need to move the building lower as there has to be another input above the building.
+    for (item, position) in exits {
+        let amount = byproducts.iter().find(|(i, _)| *i == item).map_or(0.0, |(_, a)| *a);
+        grid.add_byproduct(Byproduct { state: recipe.byproduct_state(&item), item, amount_per_sec: amount * amount_per_sec, position });
+    }
+
+    Ok(grid)
+}
+
+// Puts a row of beacons above the output belt. Beacon j sits above machine j and reaches machines j - 1 to j + 1,
+// so taking every j with j % 3 < n gives every machine exactly n beacons.
+fn place_beacons(grid: &mut Grid, step: &Step, row_length: isize) {
+    let beacons = step.modules.beacons as isize;
+    if beacons == 0 || step.modules.beacon.is_empty() {
+        return;
+    }
 
-        if fluid_input_count > 0 {
-            grid.place_inserters(1, 0, Rotation::North, row_length, building_width);
-            grid.place_entity(building_entity, 3, 1, Rotation::North, row_length, building_width);
-            grid.place_pipe_tunnel(1, 1, Rotation::North, row_length, building_width);
-            grid.place_pipe_tunnel(-1, 1, Rotation::South, row_length, building_width);
-            grid.place_pipe(-2, 0, Rotation::North, row_length * 3, 1);
+    let mut beacon = Entity::from(Building::Beacon);
+    beacon.items = step.modules.beacon_items();
+    for j in -1..=row_length {
+        if j.rem_euclid(3) < beacons {
+            grid.place_entity(beacon.clone(), -2, 1 + 3 * j, Rotation::North, 1, 0);
         }
+    }
+}
 
-    } else {
-        // grid.place_poles(1, 0, Rotation::North, row_length, building_width);
-        grid.place_inserters(1, 1, Rotation::North, row_length, building_width);
-        grid.place_entity(building_entity, 3, 1, Rotation::North, row_length, building_width);
-    }
-
-    // Assemble input rows
-    if input_count <= 2 && fluid_input_count == 0 {
-        grid.place_inserters(2 + building_height, 1, Rotation::North, row_length, building_width);
-        // grid.place_poles(2 + building_height, 0, Rotation::North, row_length, building_width);
-        grid.place_belts(3 + building_height, -1, Rotation::East, row_length * building_width + 1);
-
-
-        // Both items should go to this input, but one to the top and one to the bottom half, gonna be exciting.
-        grid.add_input(Position { x: -1, y: 4 + building_height, rotation: Rotation::South});
-        grid.add_input(Position { x: -1, y: 2 + building_height, rotation: Rotation::North});
-
-    } else if (input_count <= 4 && fluid_input_count == 0) || (input_count <= 5 && fluid_input_count == 1) {
-        
-        grid.place_inserters(2 + building_height, 1, Rotation::North, row_length, building_width);
-        // grid.place_poles(2 + building_height, 0, Rotation::North, row_length, building_width);
-        grid.place_long_inserters(2 + building_height, 2, Rotation::North, row_length, building_width);
-        grid.place_belts(3 + building_height, -1, Rotation::East, row_length * building_width + 1);
-        grid.place_belts(4 + building_height, -2, Rotation::East, row_length * building_width + 2);
-
-        // Both items should go to this input, but one to the top and one to the bottom half, gonna be exciting.
-        grid.place_belt(-1, 2 + building_height, Rotation::North);
-        grid.place_belt(-1, 1 + building_height, Rotation::North);
-        grid.place_belt(-2, 1 + building_height, Rotation::East);
-
-        grid.add_input(Position { x: -2, y: 5 + building_height, rotation: Rotation::South});
-        grid.add_input(Position { x: -2, y: 3 + building_height, rotation: Rotation::North});
-        grid.add_input(Position { x: -2, y: 2 + building_height, rotation: Rotation::South});
-        grid.add_input(Position { x: -2, y: 0 + building_height, rotation: Rotation::North});
-    } else {
-        todo!()
+// Slots next to a machine in the order they are filled, the middle one first.
+fn inserter_slots(building_width: isize) -> Vec<isize> {
+    let mut slots = vec![1, 0];
+    slots.extend(2..building_width);
+    slots.retain(|s| *s < building_width);
+    slots
+}
+
+fn place_inserter_slots(grid: &mut Grid, inserter: Entity, height: isize, rotation: Rotation, slots: &[isize], row_length: isize, building_width: isize) {
+    for slot in slots {
+        grid.place_entity(inserter.clone(), height, *slot, rotation, row_length, building_width);
+    }
+}
+
+// A row gets at most this many times the machines it needs to make its inserters keep up.
+const MAX_SPREAD: isize = 4;
+
+// Inserters next to every machine, as tier and count: those dropping the products on the output belt, and those
+// taking ingredients off the near input belt and, with long hands, off the belt behind it.
+struct Inserters {
+    output: (&'static InserterTier, usize),
+    near: (&'static InserterTier, usize),
+    far: (&'static InserterTier, usize),
+}
+
+impl Inserters {
+    // For machines making `rate` of the main product per second. Up to two solid ingredients share the near belt,
+    // with more the first two go on the far one.
+    fn per_machine(step: &Step, rate: f64) -> Result<Self, SynthesisError> {
+        let recipe = step.recipe;
+        let pick = |amount: f64, from_belt: bool, reach: isize| {
+            let (tier, count) = inserters::inserters_for(amount, from_belt, reach)
+                .ok_or_else(|| SynthesisError::unsupported(recipe.name(), format!("no electric inserter reaches {} tiles", reach)))?;
+            Ok((tier, if amount > 0.0 { count } else { 0 }))
+        };
+        let solid_byproducts: f64 = recipe.byproducts().iter().filter(|(item, _)| recipe.byproduct_state(item).is_solid()).map(|(_, a)| a).sum();
+        let output = if recipe.output_state().is_solid() { rate * (1.0 + solid_byproducts) } else { 0.0 };
+        let inputs: Vec<f64> = step.inputs.iter().filter(|i| i.state.is_solid()).map(|i| i.amount_per_sec / step.amount_per_sec * rate).collect();
+        let (far, near) = if inputs.len() <= 2 { (&inputs[..0], &inputs[..]) } else { inputs.split_at(2) };
+        Ok(Inserters { output: pick(output, false, 1)?, near: pick(near.iter().sum(), true, 1)?, far: pick(far.iter().sum(), true, 2)? })
     }
+}
 
-    grid
-}
\ No newline at end of file
+// Inserters below the machine. Regular ones take what is on the lanes of the belt next to them, long handed ones
+// reach over it to the lanes of the belt behind. Long handed inserters fill the slots from the end, regular ones from the start.
+fn place_input_inserters(grid: &mut Grid, inserters: &Inserters, height: isize, row_length: isize, building_width: isize) {
+    let slots = inserter_slots(building_width);
+    let ((near_tier, near_count), (far_tier, far_count)) = (inserters.near, inserters.far);
+    place_inserter_slots(grid, Entity::inserter(near_tier), height, Rotation::North, &slots[..near_count], row_length, building_width);
+    place_inserter_slots(grid, Entity::inserter(far_tier), height, Rotation::North, &slots[slots.len() - far_count..], row_length, building_width);
+}
@@ -1,39 +1,56 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 
-use crate::{position::Position, recipes::Recipe, building::{Building, self}, entity::{Entity, EntityPosition}, rotation::Rotation, astar::{self, Astar}};
-use rand::seq::SliceRandom; // 0.7.2
+use crate::{bounds::Bounds, occupancy::Occupancy, position::Position, recipes::Recipe, building::Building, entity::{Entity, Blueprint}, rotation::Rotation, astar::Astar, machines::MachineConfig, planner::Plan, power, error::SynthesisError};
 
 #[derive(Clone)]
 pub struct Grid {
     pub buildings: HashMap<Position, Building>,
-    pub taken: HashSet<Position>,
+    pub taken: Occupancy,
     pub copper_ore: Vec<Position>,
     pub iron_ore: Vec<Position>,
+    pub machines: MachineConfig,
 }
 
 impl Grid {
-    pub fn to_blueprint(&self) -> String {
-        let mut pub_grid = crate::grid::Grid::new(Recipe::IronOre);
-        
+    pub fn entities(&self) -> Result<Vec<Entity>, SynthesisError> {
+        let mut entities = Vec::new();
         for (pos, building) in &self.buildings {
-            let mut entity = Entity::from(building.clone());
-            entity.set_position(pos.clone());
-            pub_grid.add_entity(entity);
-        }   
+            let mut entity = match self.machines.machine_for_building(building) {
+                Some(machine) => Entity::machine(building.clone(), machine),
+                None => Entity::from(building.clone()),
+            };
+            if let Building::Assembler(recipe) | Building::ChemicalLab(recipe) | Building::Refinery(recipe) = building {
+                let recipe = Recipe::by_name(recipe).ok_or_else(|| SynthesisError::InvalidInput(format!("Unknown recipe {}", recipe)))?;
+                entity.items = self.machines.modules_for(recipe).machine_items();
+            }
+            entity.set_position(*pos);
+            entity.entity_number = entities.len();
+            entities.push(entity);
+        }
+        Ok(entities)
+    }
+
+    pub fn to_blueprint(&self) -> Result<String, SynthesisError> {
+        Ok(Blueprint::new(self.entities()?).serialize())
+    }
 
-        pub_grid.to_blueprint()
+    pub fn to_powered_blueprint(&self) -> Result<String, SynthesisError> {
+        let mut entities = self.entities()?;
+        power::electrify(&mut entities, self.machines.pole()).map_err(SynthesisError::NoPlacement)?;
+        Ok(Blueprint::new(entities).serialize())
     }
 }
 
 impl Grid {
-    pub fn new(copper: Position, iron: Position) -> Self {
+    pub fn new(copper: Position, iron: Position, machines: MachineConfig) -> Self {
         
 
         let mut grid = Self {
             buildings: HashMap::new(),
-            taken: HashSet::new(),
+            taken: Occupancy::new(),
             copper_ore: vec![copper],
             iron_ore: vec![iron],
+            machines,
         };
         grid.add(Building::Belt, copper);
         grid.add(Building::Belt, iron);
@@ -41,41 +58,32 @@ impl Grid {
     }
 
     pub fn add(&mut self, building: Building, pos: Position) {
-        self.buildings.insert(pos, building.clone());
-        let (w, h) = building.size();
-        let (ox, oy) = building.origin();
-        for x in 0..w {
-            for y in 0..h {
-                self.taken.insert(Position {x: pos.x + x - ox, y: pos.y + y - oy, rotation: Rotation::North});
-            }
-        }
+        self.taken.insert_rect(footprint(&building, pos));
+        self.buildings.insert(pos, building);
     }
 
     pub fn remove(&mut self, pos: Position) {
         let building = self.buildings.remove(&pos).unwrap();
-        let (w, h) = building.size();
-        let (ox, oy) = building.origin();
-        for x in 0..w {
-            for y in 0..h {
-                self.taken.remove(&Position {x: pos.x + x - ox, y: pos.y + y - oy, rotation: Rotation::North});
+        let b = footprint(&building, pos);
+        for x in b.min_x..b.max_x {
+            for y in b.min_y..b.max_y {
+                self.taken.remove(x, y);
             }
         }
     }
 
+    // Free tiles have to be inside the region of the machine config as well.
     pub fn is_free(&self, building: Building, pos: Position) -> bool {
-        let (w, h) = building.size();
-        let (ox, oy) = building.origin();
-        for x in 0..w {
-            for y in 0..h {
-                if self.taken.contains(&Position {x: pos.x + x - ox, y: pos.y + y - oy, rotation: Rotation::North}) {
-                    return false
-                }
-            }
-        }
-        true
+        let footprint = footprint(&building, pos);
+        !self.taken.any_in(footprint) && footprint.tiles().all(|(x, y)| self.machines.region().contains(Position::north(x, y)))
     }
 }
 
+fn footprint(building: &Building, pos: Position) -> Bounds {
+    let (w, h) = building.rotated_size(pos.rotation);
+    let (ox, oy) = building.rotated_origin(pos.rotation);
+    Bounds::with_size(pos.x - ox, pos.y - oy, w, h)
+}
 
 #[derive(Debug)]
 pub struct Possibility {
@@ -90,10 +98,24 @@ pub struct Node {
     amount_per_sec: f64,
 }
 
-pub fn generate_recipe(recipe: Recipe, amount_per_sec: f64) -> String {
+/// Searches for a layout making `amount_per_sec` of the recipe and returns it as a blueprint string.
+pub fn generate_recipe(recipe: Recipe, amount_per_sec: f64, machines: MachineConfig) -> Result<String, SynthesisError> {
+    // The moves below place inserters two tiles from the centre of a machine, which only works for 3x3 machines.
+    for step in Plan::new(recipe, amount_per_sec, &machines).map_err(SynthesisError::InvalidInput)?.steps() {
+        match step.recipe.building() {
+            Building::Miner => {}
+            Building::Assembler(_) | Building::Furnace if step.machine.size == (3, 3) => {}
+            Building::Assembler(_) | Building::Furnace => {
+                return Err(SynthesisError::unsupported(step.recipe.name(), format!("the tree generator needs 3x3 machines, not {}", step.machine.name)));
+            }
+            building => return Err(SynthesisError::unsupported(step.recipe.name(), format!("the tree generator can not place {:?}", building))),
+        }
+    }
+
     let mut grid = Grid::new(
         Position { x: -2, y: 0, rotation: Rotation::South},
-        Position { x: -4, y: 0, rotation: Rotation::South}
+        Position { x: -4, y: 0, rotation: Rotation::South},
+        machines,
     );
     let building = Building::Belt;
 
@@ -105,26 +127,34 @@ pub fn generate_recipe(recipe: Recipe, amount_per_sec: f64) -> String {
     };
     grid.add(node.building.clone(), node.pos);
 
-    generate(&mut grid, node).unwrap().to_blueprint()
+    let mut budget = SEARCH_BUDGET;
+    let grid = generate(&mut grid, node, &mut budget)?.ok_or_else(|| SynthesisError::NoPlacement(format!("no room for the machines of {}", recipe.name())))?;
+    grid.to_powered_blueprint()
 }
 
-fn generate(grid: &mut Grid, node: Node) -> Option<Grid> {
+// Nodes the search may try before it gives up. Without a region it always finds room, inside one it can backtrack
+// through every placement.
+const SEARCH_BUDGET: usize = 100_000;
+
+fn generate(grid: &mut Grid, node: Node, budget: &mut usize) -> Result<Option<Grid>, SynthesisError> {
+    if *budget == 0 {
+        return Err(SynthesisError::NoPlacement(format!("the search ran out of its budget of {} nodes", SEARCH_BUDGET)));
+    }
+    *budget -= 1;
     if node.recipe.building() == Building::Miner && node.building == Building::Inserter {
         // Route to input of raw resource
-        return Some(grid.clone());
-        return route_raw_resource(grid.clone(), node);
+        // return route_raw_resource(grid.clone(), node);
+        return Ok(Some(grid.clone()));
     }
 
 
-    // println!("inbetween blueprint: {}", grid.to_blueprint());
-    let mut moves = moves(node, grid);
-    if moves.len() == 0 {
-        // println!("No moves available!\n");
-        return None;
+    let moves = moves(node, grid)?;
+    if moves.is_empty() {
+        return Ok(None);
     }
 
     // Add new nodes to queue, try, and remove if failure.
-    for mut possibility in moves {
+    for possibility in moves {
         // Try adding both heads.
 
         if possibility.heads.len() > 1 {
@@ -139,36 +169,36 @@ fn generate(grid: &mut Grid, node: Node) -> Option<Grid> {
 
             let mut failed_one = false;
             for head in &possibility.heads {
-                match generate(&mut new_grid, head.clone()) {
+                match generate(&mut new_grid, head.clone(), budget)? {
                     None => { failed_one = true; break; }
                     Some(grid) => { new_grid = grid }
                 }
             }
             match failed_one {
                 true => continue,
-                false => return Some(new_grid)
+                false => return Ok(Some(new_grid))
             }
         } else {
             grid.add(possibility.heads[0].building.clone(), possibility.heads[0].pos);
-            let result = generate(grid, possibility.heads[0].clone());
+            let result = generate(grid, possibility.heads[0].clone(), budget);
             grid.remove(possibility.heads[0].pos);
-            match result {
+            match result? {
                 None => continue,
-                Some(grid) => return Some(grid),
+                Some(grid) => return Ok(Some(grid)),
             }
         }
     }
-    None
+    Ok(None)
 }
 
-fn moves(node: Node, grid: &Grid) -> Vec<Possibility> {
-    match node.building {
-        Building::Assembler(_) => { println!("Assembler moves"); assembler_moves(node, grid) }
-        Building::Belt => { println!("belt moves"); belt_moves(node, grid) }
-        Building::Inserter => { println!("inserter moves"); inserter_moves(node, grid) }
-        Building::Furnace => { println!("furnace moves"); assembler_moves(node, grid) }
-        _ => { unimplemented!("These buildings should not occur yet")}
-    }
+fn moves(node: Node, grid: &Grid) -> Result<Vec<Possibility>, SynthesisError> {
+    Ok(match node.building {
+        Building::Assembler(_) => assembler_moves(node, grid),
+        Building::Belt => belt_moves(node, grid),
+        Building::Inserter => inserter_moves(node, grid),
+        Building::Furnace => assembler_moves(node, grid),
+        building => return Err(SynthesisError::unsupported(node.recipe.name(), format!("the tree generator can not place {:?}", building))),
+    })
 }
 
 
@@ -206,10 +236,7 @@ fn inserter_moves(node: Node, grid: &Grid) -> Vec<Possibility> {
 
     let mut possibilities = Vec::new();
     // Assembler
-    let possible_centers = vec![new_pos]; //, new_pos.rotate_left().backward(), new_pos.rotate_right().backward()];
-    println!("New pos: {:?}", new_pos);
-    println!("Node pos: {:?}", node.pos);
-    println!("Possible centers: {:?}", possible_centers);
+    let possible_centers = vec![new_pos]; //, new_pos.turned(Rotation::West).backward(), new_pos.turned(Rotation::East).backward()];
     for center in possible_centers {        
         if grid.is_free(node.recipe.building(), center) {
             let mut heads = Vec::new();
@@ -218,11 +245,11 @@ fn inserter_moves(node: Node, grid: &Grid) -> Vec<Possibility> {
                     recipe: r.0,
                     building: node.recipe.building(),
                     pos: center,
-                    amount_per_sec: node.amount_per_sec * r.1
+                    amount_per_sec: node.amount_per_sec * r.1 / grid.machines.productivity(node.recipe)
                 })
             }
             
-            possibilities.push(Possibility { heads: heads })
+            possibilities.push(Possibility { heads })
         }
     }
 
@@ -250,15 +277,14 @@ fn belt_moves(node: Node, grid: &Grid) -> Vec<Possibility> {
 
     // We can place inserters putting something on the belts. This is a split where we have 2 heads, with reduced amount per sec, for now only consider belt backwards and inserters from the sides for convenience.
     
-    let single_assembler_amount_per_sec = 1.0 / node.recipe.crafting_time();
+    let speed = grid.machines.crafting_speed(node.recipe).unwrap() * grid.machines.productivity(node.recipe);
+    let single_assembler_amount_per_sec = speed / node.recipe.crafting_time();
     let leftover_amount_per_sec = node.amount_per_sec - single_assembler_amount_per_sec;
 
     if leftover_amount_per_sec > 0.0 {
-        println!("Belt split is needed");
         let belt_position = node.pos.backward();
         if grid.is_free(Building::Belt, belt_position) {
-            let inserter_position = node.pos.rotate_left().backward();
-            println!("node position: {:?}, inserter_position: {:?}", node.pos, inserter_position);
+            let inserter_position = node.pos.turned(Rotation::West).backward();
             if grid.is_free(Building::Inserter, inserter_position) {
                 possibilities.push(Possibility { heads: vec![
                 Node {
@@ -276,7 +302,7 @@ fn belt_moves(node: Node, grid: &Grid) -> Vec<Possibility> {
                 ]})
             }
 
-            let inserter_position = node.pos.rotate_right().backward();
+            let inserter_position = node.pos.turned(Rotation::East).backward();
             if grid.is_free(Building::Inserter, inserter_position) {
                 possibilities.push(Possibility { heads: vec![
                 Node {
@@ -296,9 +322,6 @@ fn belt_moves(node: Node, grid: &Grid) -> Vec<Possibility> {
         }
     } else {
         // Just need single inserter unit without belt split.
-        println!("No belt split needed");
-
-
         let inserter_position = node.pos.backward();
         if grid.is_free(Building::Inserter, inserter_position) {
             let n = Node {
@@ -310,7 +333,7 @@ fn belt_moves(node: Node, grid: &Grid) -> Vec<Possibility> {
             possibilities.push(Possibility { heads: vec![n] });
         }
 
-        let inserter_position = node.pos.rotate_right().backward();
+        let inserter_position = node.pos.turned(Rotation::East).backward();
         if grid.is_free(Building::Inserter, inserter_position) {
             let n = Node {
                 recipe: node.recipe,
@@ -321,7 +344,7 @@ fn belt_moves(node: Node, grid: &Grid) -> Vec<Possibility> {
             possibilities.push(Possibility { heads: vec![n] });
         }
 
-        let inserter_position = node.pos.rotate_left().backward();
+        let inserter_position = node.pos.turned(Rotation::West).backward();
         if grid.is_free(Building::Inserter, inserter_position) {
             let n = Node {
                 recipe: node.recipe,
@@ -344,7 +367,7 @@ fn belt_moves(node: Node, grid: &Grid) -> Vec<Possibility> {
         }]})
     }
 
-    let position = node.pos.rotate_right().backward();
+    let position = node.pos.turned(Rotation::East).backward();
     if grid.is_free(Building::Belt, position) {
         possibilities.push(Possibility { heads: vec![Node {
             recipe: node.recipe,
@@ -354,7 +377,7 @@ fn belt_moves(node: Node, grid: &Grid) -> Vec<Possibility> {
         }]})
     }
 
-    let position = node.pos.rotate_left().backward();
+    let position = node.pos.turned(Rotation::West).backward();
     if grid.is_free(Building::Belt, position) {
         possibilities.push(Possibility { heads: vec![Node {
             recipe: node.recipe,
@@ -367,64 +390,68 @@ fn belt_moves(node: Node, grid: &Grid) -> Vec<Possibility> {
 }
 
 
-fn route_raw_resource(mut grid: Grid, node: Node) -> Option<Grid> {
-    let path = match node.recipe {
-        Recipe::IronOre => grid.iron_ore.clone(),
-        Recipe::CopperOre => grid.copper_ore.clone(),
+// Not part of the search yet, ore is fed from outside the blueprint.
+#[allow(dead_code)]
+fn route_raw_resource(mut grid: Grid, node: Node) -> Result<Option<Grid>, SynthesisError> {
+    let path = match node.recipe.name() {
+        "iron-ore" => grid.iron_ore.clone(),
+        "copper-ore" => grid.copper_ore.clone(),
 
-        _ => panic!("Asking for non raw resource")
+        name => return Err(SynthesisError::InvalidInput(format!("{} is not a raw resource", name)))
     };
 
     if path.len() == 1 {
         // Just route directly to the end.
-        return match Astar::new(path[0].forward(), node.pos.backward(), grid.taken.clone()).astar_belt() {
-            Some(path) => {
+        let mut astar = Astar::new(path[0].forward(), node.pos.backward(), &grid.taken);
+        astar.set_region(grid.machines.region().clone());
+        return Ok(match astar.astar_belt() {
+            Ok(path) => {
                 grid.add(Building::Belt, path[0].forward());
                 for p in &path {
                     grid.add(Building::Belt, *p);
                 }
                 // Add path to the grid resources as well.
-                match node.recipe {
-                    Recipe::IronOre => grid.iron_ore.append(&mut path.clone()),
-                    Recipe::CopperOre => grid.copper_ore.append(&mut path.clone()),
+                match node.recipe.name() {
+                    "iron-ore" => grid.iron_ore.append(&mut path.clone()),
+                    "copper-ore" => grid.copper_ore.append(&mut path.clone()),
 
                     _ => {}
                 }
                 Some(grid)
             },
-            None => None
-        }
+            Err(_) => None
+        });
     }
 
     for pos in path {
         // try inserter
-        if !grid.is_free(Building::Inserter, pos.rotate_left().forward()) { continue; }
-        if !grid.is_free(Building::Belt, pos.rotate_left().forward().forward()) { continue; }
-        grid.add(Building::Inserter, pos.rotate_left().forward());
-        let start = pos.rotate_left().forward().forward();
+        if !grid.is_free(Building::Inserter, pos.turned(Rotation::West).forward()) { continue; }
+        if !grid.is_free(Building::Belt, pos.turned(Rotation::West).forward().forward()) { continue; }
+        grid.add(Building::Inserter, pos.turned(Rotation::West).forward());
+        let start = pos.turned(Rotation::West).forward().forward();
         let end = node.pos.backward();
 
-        match Astar::new(start, end, grid.taken.clone()).astar_belt() {
-            Some(path) => {
+        let mut astar = Astar::new(start, end, &grid.taken);
+        astar.set_region(grid.machines.region().clone());
+        match astar.astar_belt() {
+            Ok(path) => {
                 for p in &path {
                     grid.add(Building::Belt, *p);
                 }
 
-                match node.recipe {
-                    Recipe::IronOre => grid.iron_ore.append(&mut path.clone()),
-                    Recipe::CopperOre => grid.copper_ore.append(&mut path.clone()),
+                match node.recipe.name() {
+                    "iron-ore" => grid.iron_ore.append(&mut path.clone()),
+                    "copper-ore" => grid.copper_ore.append(&mut path.clone()),
 
                     _ => {}
                 }
 
-                println!("Finished first path: {}", grid.to_blueprint());
-                panic!("Stopping");
-                return Some(grid);
+                return Ok(Some(grid));
             },
-            None => continue,
+            Err(_) => continue,
         }
         
     }
 
-    None
+    Ok(None)
 }
\ No newline at end of file
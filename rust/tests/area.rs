@@ -0,0 +1,36 @@
+use rust::astar::Region;
+use rust::bounds::Bounds;
+use rust::error::SynthesisError;
+use rust::grid::Grid;
+use rust::machines::MachineConfig;
+use rust::planner::Plan;
+use rust::recipes::Recipe;
+use rust::synthesize;
+
+fn synth_in(area: Bounds) -> Result<Grid, SynthesisError> {
+    let mut config = MachineConfig::default();
+    config.set_region(Region::Rect(area));
+    let plan = Plan::new(Recipe::producer("iron-gear-wheel").unwrap(), 2.0, &config).unwrap();
+    synthesize::synth(&plan)
+}
+
+#[test]
+fn layouts_stay_inside_the_build_area() {
+    let area = Bounds { min_x: -10, max_x: 20, min_y: -10, max_y: 40 };
+    let grid = synth_in(area).unwrap();
+    for entity in grid.collect_entities() {
+        assert!(entity.bounds().tiles().all(|(x, y)| area.contains(x, y)), "{} at {:?}", entity.name, entity.position);
+    }
+
+    // The rows alone are larger than this.
+    assert!(synth_in(Bounds { min_x: 0, max_x: 5, min_y: 0, max_y: 5 }).is_err());
+}
+
+#[test]
+fn layouts_move_into_an_area_away_from_the_origin() {
+    let area = Bounds { min_x: 500, max_x: 560, min_y: -300, max_y: -250 };
+    let grid = synth_in(area).unwrap();
+    for entity in grid.collect_entities() {
+        assert!(entity.bounds().tiles().all(|(x, y)| area.contains(x, y)), "{} at {:?}", entity.name, entity.position);
+    }
+}
@@ -0,0 +1,50 @@
+use rust::entity::Blueprint;
+use serde_json::json;
+
+// A labelled blueprint with module requests, an underground belt pair and fields we do not model, like wires and icons.
+const LABELLED: &str = r#"{"blueprint": {
+    "label": "Gears",
+    "icons": [{"signal": {"type": "item", "name": "iron-gear-wheel"}, "index": 1}],
+    "entities": [
+        {"entity_number": 1, "name": "assembling-machine-2", "position": {"x": 1.5, "y": 1.5}, "recipe": "iron-gear-wheel",
+            "items": {"speed-module": 2}},
+        {"entity_number": 2, "name": "underground-belt", "position": {"x": 3.5, "y": 0.5}, "direction": 2, "type": "input"},
+        {"entity_number": 3, "name": "underground-belt", "position": {"x": 7.5, "y": 0.5}, "direction": 2, "type": "output"},
+        {"entity_number": 4, "name": "small-electric-pole", "position": {"x": 3.5, "y": 2.5},
+            "connections": {"1": {"green": [{"entity_id": 1}]}}, "control_behavior": {"circuit_enable_disable": true}}
+    ],
+    "item": "blueprint",
+    "version": 281479276199938
+}}"#;
+
+#[test]
+fn decode_of_encode_gives_the_same_blueprint() {
+    let blueprint = Blueprint::from_json(LABELLED).unwrap();
+    let encoded = blueprint.serialize();
+    let decoded = Blueprint::decode(&encoded).unwrap();
+    assert_eq!(decoded, blueprint);
+    assert_eq!(decoded.serialize(), encoded);
+}
+
+#[test]
+fn unknown_fields_survive_the_round_trip() {
+    let blueprint = Blueprint::decode(&Blueprint::from_json(LABELLED).unwrap().serialize()).unwrap();
+    assert_eq!(blueprint.extra["label"], json!("Gears"));
+    assert_eq!(blueprint.extra["icons"][0]["signal"]["name"], json!("iron-gear-wheel"));
+
+    let [assembler, entrance, exit, pole] = &blueprint.entities[..] else {
+        panic!("expected four entities, got {}", blueprint.entities.len());
+    };
+    assert_eq!(assembler.items.get("speed-module"), Some(&2));
+    assert_eq!(assembler.recipe, "iron-gear-wheel");
+    assert_eq!((entrance.kind.as_str(), exit.kind.as_str()), ("input", "output"));
+    assert_eq!(entrance.direction, 2);
+    assert_eq!(pole.extra["connections"]["1"]["green"][0]["entity_id"], json!(1));
+    assert_eq!(pole.extra["control_behavior"], json!({"circuit_enable_disable": true}));
+
+    // The fields are written back where they came from, not as part of another field.
+    let written: serde_json::Value = serde_json::from_str(&blueprint.to_json()).unwrap();
+    assert_eq!(written["blueprint"]["label"], json!("Gears"));
+    assert_eq!(written["blueprint"]["entities"][1]["type"], json!("input"));
+    assert!(written["blueprint"]["entities"][0].get("extra").is_none());
+}
@@ -0,0 +1,103 @@
+use std::collections::HashSet;
+
+use proptest::prelude::*;
+use rust::bounds::Bounds;
+use rust::rotation::Rotation;
+
+// Small rectangles around the origin, sizes of zero and below make empty ones.
+fn rect() -> impl Strategy<Value = Bounds> {
+    (-20isize..20, -20isize..20, -2isize..8, -2isize..8).prop_map(|(x, y, w, h)| Bounds { min_x: x, max_x: x + w, min_y: y, max_y: y + h })
+}
+
+// The tiles of a rectangle as the model its operations are checked against.
+fn tiles(b: Bounds) -> HashSet<(isize, isize)> {
+    let mut tiles = HashSet::new();
+    for x in b.min_x..b.max_x {
+        for y in b.min_y..b.max_y {
+            tiles.insert((x, y));
+        }
+    }
+    tiles
+}
+
+proptest! {
+    #[test]
+    fn tiles_and_area(a in rect()) {
+        let covered: HashSet<_> = a.tiles().collect();
+        prop_assert_eq!(&covered, &tiles(a));
+        prop_assert_eq!(a.area() as usize, covered.len());
+        prop_assert_eq!(a.is_empty(), covered.is_empty());
+        prop_assert!(covered.iter().all(|(x, y)| a.contains(*x, *y)));
+    }
+
+    #[test]
+    fn intersection_is_the_shared_tiles(a in rect(), b in rect()) {
+        let shared: HashSet<_> = tiles(a).intersection(&tiles(b)).copied().collect();
+        prop_assert_eq!(tiles(a.intersection(b)), shared.clone());
+        prop_assert_eq!(a.intersects(b), !shared.is_empty());
+        prop_assert_eq!(a.intersects(b), b.intersects(a));
+        prop_assert_eq!(a.intersection(b), b.intersection(a));
+    }
+
+    #[test]
+    fn union_is_the_smallest_rectangle_around_both(a in rect(), b in rect()) {
+        let u = a.union(b);
+        prop_assert_eq!(u, b.union(a));
+        prop_assert!(u.contains_rect(a) && u.contains_rect(b));
+        // Every side of the union touches a tile of one of the two.
+        let both: HashSet<_> = tiles(a).union(&tiles(b)).copied().collect();
+        if let (Some(min_x), Some(max_x)) = (both.iter().map(|t| t.0).min(), both.iter().map(|t| t.0).max()) {
+            prop_assert_eq!((u.min_x, u.max_x), (min_x, max_x + 1));
+        } else {
+            prop_assert!(u.is_empty());
+        }
+        prop_assert_eq!(a.union(Bounds::new()), a.union(a));
+    }
+
+    #[test]
+    fn containment_matches_the_tiles(a in rect(), b in rect()) {
+        prop_assert_eq!(a.contains_rect(b), tiles(b).is_subset(&tiles(a)));
+        // Rectangles inside each other collide too.
+        if a.contains_rect(b) && !b.is_empty() {
+            prop_assert!(a.intersects(b));
+        }
+    }
+
+    #[test]
+    fn expand_and_shrink(a in rect(), margin in 0isize..5) {
+        let grown = a.expand(margin);
+        prop_assert!(grown.contains_rect(a));
+        prop_assert_eq!(grown.expand(-margin), a.union(Bounds::new()));
+        if !a.is_empty() {
+            prop_assert_eq!(grown.width(), a.width() + 2 * margin);
+        }
+    }
+
+    #[test]
+    fn moves_rotations_and_mirrors_keep_the_size(a in rect(), x in -50isize..50, y in -50isize..50) {
+        prop_assert_eq!(a.move_rel(x, y).area(), a.area());
+        prop_assert_eq!(a.move_rel(x, y).move_rel(-x, -y), a.union(Bounds::new()));
+        prop_assert_eq!(a.mirror_around(x).mirror_around(x), a.union(Bounds::new()));
+        let mut turned = a;
+        for _ in 0..4 {
+            turned = turned.rotate_around((x, y), Rotation::East);
+            prop_assert_eq!(turned.area(), a.area());
+        }
+        prop_assert_eq!(turned, a.union(Bounds::new()));
+    }
+}
+
+#[test]
+fn empty_rectangles() {
+    let empty = Bounds::new();
+    assert!(empty.is_empty());
+    assert_eq!(empty.area(), 0);
+    assert_eq!(empty.tiles().count(), 0);
+
+    // The empty rectangle no longer pulls the origin into a union.
+    let far = Bounds::with_size(10, 10, 2, 2);
+    assert_eq!(empty.union(far), far);
+    assert_eq!(Bounds::with_size(3, 3, 0, 5), empty);
+    assert!(!far.intersects(empty));
+    assert!(far.contains_rect(empty));
+}
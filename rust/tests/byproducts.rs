@@ -0,0 +1,197 @@
+use std::collections::{HashMap, HashSet};
+
+use rust::building::Building;
+use rust::entity::Entity;
+use rust::grid::Grid;
+use rust::machines::MachineConfig;
+use rust::planner::Plan;
+use rust::position::Position;
+use rust::recipes::{self, Recipe, RecipeDb};
+use rust::rotation::Rotation;
+use rust::synthesize;
+
+// Oil processing with a consumer of one of its byproducts, and a made up recipe with a solid byproduct.
+const RECIPES: &str = r#"{"recipe": {
+    "advanced-oil-processing": {"name": "advanced-oil-processing", "category": "oil-processing", "energy_required": 5,
+        "ingredients": [{"type": "fluid", "name": "water", "amount": 50}, {"type": "fluid", "name": "crude-oil", "amount": 100}],
+        "results": [{"type": "fluid", "name": "heavy-oil", "amount": 25}, {"type": "fluid", "name": "light-oil", "amount": 45},
+            {"type": "fluid", "name": "petroleum-gas", "amount": 55}]},
+    "sulfur": {"name": "sulfur", "category": "chemistry", "energy_required": 1,
+        "ingredients": [{"type": "fluid", "name": "water", "amount": 30}, {"type": "fluid", "name": "petroleum-gas", "amount": 30}],
+        "results": [{"type": "item", "name": "sulfur", "amount": 2}]},
+    "iron-plate": {"name": "iron-plate", "category": "smelting", "energy_required": 3.2, "ingredients": [["iron-ore", 1]], "result": "iron-plate"},
+    "gear-and-stick": {"name": "gear-and-stick", "category": "crafting", "energy_required": 1, "ingredients": [["iron-plate", 3]],
+        "results": [{"type": "item", "name": "iron-gear-wheel", "amount": 1}, {"type": "item", "name": "iron-stick", "amount": 2}]}
+},
+"fluid": {"water": {}, "crude-oil": {}, "heavy-oil": {}, "light-oil": {}, "petroleum-gas": {}},
+"resource": {
+    "iron-ore": {"minable": {"mining_time": 1, "result": "iron-ore"}},
+    "crude-oil": {"category": "basic-fluid", "minable": {"mining_time": 1, "results": [{"type": "fluid", "name": "crude-oil", "amount": 10}]}}
+}}"#;
+
+fn synth(recipe: &str, rate: f64) -> Grid {
+    // Every test installs the same database, only the first one gets to.
+    let _ = recipes::install(RecipeDb::from_json(RECIPES).unwrap());
+    let plan = Plan::new(Recipe::by_name(recipe).unwrap(), rate, &MachineConfig::default()).unwrap();
+    synthesize::synth(&plan).unwrap()
+}
+
+fn offset(rotation: Rotation, (x, y): (isize, isize)) -> (isize, isize) {
+    let (dx, dy) = rotation.offset();
+    (x + dx, y + dy)
+}
+
+// Groups pipes into the networks the game would connect. Pipes connect to every pipe next to them,
+// pipe-to-grounds only on the side they face and underground to the next one facing back within reach.
+fn pipe_networks(entities: &[Entity]) -> Vec<HashSet<(isize, isize)>> {
+    let mut pipes: HashMap<(isize, isize), Option<Rotation>> = HashMap::new();
+    for entity in entities {
+        match entity.building {
+            Building::Pipe => { pipes.insert((entity.position.x, entity.position.y), None); }
+            Building::PipeTunnel => { pipes.insert((entity.position.x, entity.position.y), Some(Rotation::from_direction(entity.direction))); }
+            _ => {}
+        }
+    }
+    let opens_to = |tile: (isize, isize), side: Rotation| match pipes.get(&tile) {
+        Some(None) => true,
+        Some(Some(facing)) => *facing == side,
+        None => false,
+    };
+
+    let mut neighbours: HashMap<(isize, isize), Vec<(isize, isize)>> = HashMap::new();
+    for (tile, facing) in &pipes {
+        let sides = match facing {
+            None => Rotation::CARDINAL.to_vec(),
+            Some(facing) => vec![*facing],
+        };
+        for side in sides {
+            let next = offset(side, *tile);
+            if opens_to(next, side.compose(Rotation::South)) {
+                neighbours.entry(*tile).or_default().push(next);
+            }
+        }
+        if let Some(facing) = facing {
+            let down = facing.compose(Rotation::South);
+            let mut next = *tile;
+            for _ in 0..rust::astar::PIPE_TUNNEL_REACH {
+                next = offset(down, next);
+                if pipes.get(&next) == Some(&Some(down)) {
+                    neighbours.entry(*tile).or_default().push(next);
+                    break;
+                }
+            }
+        }
+    }
+
+    let mut networks = Vec::new();
+    let mut seen = HashSet::new();
+    for start in pipes.keys() {
+        if !seen.insert(*start) {
+            continue;
+        }
+        let (mut network, mut open) = (HashSet::from([*start]), vec![*start]);
+        while let Some(tile) = open.pop() {
+            for next in neighbours.get(&tile).into_iter().flatten() {
+                if seen.insert(*next) {
+                    network.insert(*next);
+                    open.push(*next);
+                }
+            }
+        }
+        networks.push(network);
+    }
+    networks
+}
+
+// The fluid in every network, by what the pipes were laid for.
+fn network_fluids(grid: &Grid) -> Vec<HashSet<String>> {
+    let fluids: HashMap<(isize, isize), String> = grid.fluid_tiles().into_iter().map(|(p, fluid)| ((p.x, p.y), fluid)).collect();
+    pipe_networks(&grid.collect_entities()).iter()
+        .map(|network| network.iter().filter_map(|tile| fluids.get(tile).cloned()).collect())
+        .collect()
+}
+
+#[test]
+fn oil_products_never_share_a_pipe_network() {
+    let grid = synth("advanced-oil-processing", 2.0);
+    let networks = network_fluids(&grid);
+    for fluids in &networks {
+        assert_eq!(fluids.len(), 1, "one network carries {:?}", fluids);
+    }
+    let carried: HashSet<&String> = networks.iter().flatten().collect();
+    for fluid in ["heavy-oil", "light-oil", "petroleum-gas"] {
+        assert!(carried.contains(&fluid.to_string()), "no pipe carries {}", fluid);
+    }
+
+    // Heavy oil is the main product, the others leave on pipes of their own.
+    let mut byproducts: Vec<&str> = grid.byproducts().iter().map(|b| b.item.as_str()).collect();
+    byproducts.sort();
+    assert_eq!(byproducts, ["light-oil", "petroleum-gas"]);
+    let output = grid.output().unwrap();
+    assert!(grid.byproducts().iter().all(|b| (b.position.x, b.position.y) != (output.x, output.y)));
+}
+
+#[test]
+fn byproducts_feed_the_rows_consuming_them() {
+    let grid = synth("sulfur", 1.0);
+    for fluids in network_fluids(&grid) {
+        assert_eq!(fluids.len(), 1, "one network carries {:?}", fluids);
+    }
+
+    // Petroleum gas goes into the sulfur row, heavy and light oil are left over.
+    let mut left_over: Vec<&str> = grid.byproducts().iter().map(|b| b.item.as_str()).collect();
+    left_over.sort();
+    assert_eq!(left_over, ["heavy-oil", "light-oil"]);
+    let fluids = grid.fluid_tiles();
+    let petroleum_next_to_plants = grid.collect_entities().iter()
+        .filter(|e| e.building == Building::ChemicalLab(String::from("sulfur")))
+        .flat_map(|e| e.bounds().expand(1).tiles().collect::<Vec<_>>())
+        .any(|(x, y)| fluids.get(&Position::north(x, y)).is_some_and(|f| f == "petroleum-gas"));
+    assert!(petroleum_next_to_plants);
+}
+
+#[test]
+fn solid_byproducts_leave_on_a_belt_of_their_own() {
+    let grid = synth("gear-and-stick", 1.0);
+    let entities = grid.collect_entities();
+    let splitter = entities.iter().find(|e| e.building == Building::Splitter).expect("no splitter filters the byproduct");
+    assert_eq!(splitter.extra["filter"], "iron-stick");
+
+    let [stick] = grid.byproducts() else {
+        panic!("expected one byproduct, got {}", grid.byproducts().len());
+    };
+    assert_eq!(stick.item, "iron-stick");
+    assert!((stick.amount_per_sec - 2.0).abs() < 1e-9);
+    // Both belts start right behind the splitter, one on each of its sides.
+    let output = grid.output().unwrap();
+    let behind = |p: &Position| offset(Rotation::from_direction(splitter.direction).compose(Rotation::South), (p.x, p.y));
+    let (stick_from, output_from) = (behind(&stick.position), behind(&output));
+    assert!(splitter.bounds().contains(stick_from.0, stick_from.1));
+    assert!(splitter.bounds().contains(output_from.0, output_from.1));
+    assert_ne!(stick_from, output_from);
+}
+
+// Which of the splitter's two tiles each belt leaves from has to match the side its priority sends the filtered item
+// to, also once the row is mirrored.
+#[test]
+fn mirrored_rows_filter_the_byproduct_onto_its_own_belt() {
+    let mut grid = synth("gear-and-stick", 1.0);
+    for mirrored in [false, true] {
+        if mirrored {
+            grid.mirror();
+        }
+        let entities = grid.collect_entities();
+        let splitter = entities.iter().find(|e| e.building == Building::Splitter).expect("no splitter filters the byproduct");
+        let facing = Rotation::from_direction(splitter.direction);
+        let side = match splitter.extra["output_priority"].as_str() {
+            Some("left") => facing.compose(Rotation::West),
+            Some("right") => facing.compose(Rotation::East),
+            other => panic!("unexpected output priority {other:?}"),
+        };
+        let behind = |p: &Position| offset(facing.compose(Rotation::South), (p.x, p.y));
+        let (stick_from, output_from) = (behind(&grid.byproducts()[0].position), behind(&grid.output().unwrap()));
+        let (dx, dy) = side.offset();
+        let towards_side = (stick_from.0 - output_from.0) * dx + (stick_from.1 - output_from.1) * dy;
+        assert!(towards_side > 0, "the iron sticks leave on the other belt (mirrored: {mirrored})");
+    }
+}
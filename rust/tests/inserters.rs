@@ -0,0 +1,39 @@
+use rust::building::Building;
+use rust::inserters;
+use rust::machines::MachineConfig;
+use rust::planner::Plan;
+use rust::recipes::{self, Recipe, RecipeDb};
+use rust::synthesize;
+
+// A recipe with three ingredients, two of which come from the belt behind the near one.
+const RECIPES: &str = r#"{"recipe": {
+    "widget": {"name": "widget", "category": "crafting", "energy_required": 0.5,
+        "ingredients": [["iron-ore", 2], ["copper-ore", 2], ["stone", 2]], "result": "widget"}
+},
+"resource": {
+    "iron-ore": {"minable": {"mining_time": 1, "result": "iron-ore"}},
+    "copper-ore": {"minable": {"mining_time": 1, "result": "copper-ore"}},
+    "stone": {"minable": {"mining_time": 1, "result": "stone"}}
+}}"#;
+
+#[test]
+fn rows_get_more_machines_when_inserters_can_not_keep_up() {
+    recipes::install(RecipeDb::from_json(RECIPES).unwrap()).unwrap();
+    let plan = Plan::new(Recipe::by_name("widget").unwrap(), 2.0, &MachineConfig::default()).unwrap();
+    let step = plan.steps().iter().find(|s| s.recipe.name() == "widget").unwrap();
+
+    // Running at full speed, a machine needs four long handed inserters for the far belt alone.
+    let grid = synthesize::synth(&plan).unwrap();
+    let entities = grid.collect_entities();
+    let machines = entities.iter().filter(|e| e.building == step.recipe.building()).count();
+    assert!(machines > step.machine_count, "{} machines for {} planned", machines, step.machine_count);
+    let long_handed = entities.iter().filter(|e| e.building == Building::LongInserter).count();
+    assert_eq!(long_handed, machines * 2);
+}
+
+#[test]
+fn reaches_without_an_electric_inserter_have_none() {
+    let (tier, count) = inserters::inserters_for(1.0, true, 2).unwrap();
+    assert_eq!((tier.name, count), ("long-handed-inserter", 1));
+    assert!(inserters::inserters_for(1.0, true, 3).is_none());
+}
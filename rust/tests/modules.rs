@@ -0,0 +1,31 @@
+use rust::machines::{self, MachineConfig, ModuleConfig};
+
+fn modules(names: &[&str]) -> ModuleConfig {
+    ModuleConfig { machine: names.iter().map(|n| machines::module(n).unwrap()).collect(), ..ModuleConfig::default() }
+}
+
+#[test]
+fn modules_have_to_fit_the_machine() {
+    let mut config = MachineConfig::default();
+    // The first assembling machine has no module slots at all.
+    assert!(config.set_modules("iron-gear-wheel", modules(&["speed-module"])).is_err());
+
+    config.use_machine("assembling-machine-2").unwrap();
+    config.set_modules("iron-gear-wheel", modules(&["speed-module", "speed-module"])).unwrap();
+    let error = config.set_modules("iron-gear-wheel", modules(&["speed-module"; 3])).unwrap_err();
+    assert!(error.contains("2 module slots"), "{}", error);
+    assert!(config.set_modules("no-such-recipe", modules(&[])).is_err());
+}
+
+#[test]
+fn productivity_only_goes_into_intermediates() {
+    let mut config = MachineConfig::default();
+    config.use_machine("assembling-machine-2").unwrap();
+    config.set_modules("iron-gear-wheel", modules(&["productivity-module"])).unwrap();
+    config.set_modules("iron-plate", modules(&["productivity-module-3", "productivity-module-3"])).unwrap();
+
+    let error = config.set_modules("fast-inserter", modules(&["productivity-module"])).unwrap_err();
+    assert!(error.contains("intermediates"), "{}", error);
+    // Speed modules are fine everywhere.
+    config.set_modules("fast-inserter", modules(&["speed-module"])).unwrap();
+}
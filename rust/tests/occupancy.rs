@@ -0,0 +1,69 @@
+use std::collections::HashSet;
+
+use rust::bounds::Bounds;
+use rust::occupancy::Occupancy;
+
+fn rect_tiles(b: Bounds) -> impl Iterator<Item = (isize, isize)> {
+    (b.min_x..b.max_x).flat_map(move |x| (b.min_y..b.max_y).map(move |y| (x, y)))
+}
+
+// Rectangles around and across chunk borders, negative coordinates included.
+fn rects() -> Vec<Bounds> {
+    vec![
+        Bounds { min_x: 0, max_x: 1, min_y: 0, max_y: 1 },
+        Bounds { min_x: -3, max_x: 3, min_y: -1, max_y: 2 },
+        Bounds { min_x: 30, max_x: 34, min_y: 31, max_y: 33 },
+        Bounds { min_x: -70, max_x: -2, min_y: 5, max_y: 6 },
+        Bounds { min_x: 0, max_x: 32, min_y: 0, max_y: 32 },
+        Bounds { min_x: 5, max_x: 5, min_y: 0, max_y: 10 },
+    ]
+}
+
+#[test]
+fn matches_a_set_of_tiles() {
+    let mut occupancy = Occupancy::new();
+    let mut tiles = HashSet::new();
+    for b in rects() {
+        occupancy.insert_rect(b);
+        tiles.extend(rect_tiles(b));
+        assert_eq!(occupancy.len(), tiles.len());
+    }
+    assert_eq!(occupancy.tiles().collect::<HashSet<_>>(), tiles);
+    for x in -80..80 {
+        for y in -40..40 {
+            assert_eq!(occupancy.contains(x, y), tiles.contains(&(x, y)));
+        }
+    }
+
+    for (x, y) in [(0, 0), (31, 31), (-70, 5), (100, 100)] {
+        assert_eq!(occupancy.remove(x, y), tiles.remove(&(x, y)));
+    }
+    assert_eq!(occupancy.len(), tiles.len());
+    assert_eq!(occupancy, tiles.iter().copied().collect());
+}
+
+#[test]
+fn rectangle_queries() {
+    let occupancy: Occupancy = [(0, 0), (33, -1), (-40, 64)].into_iter().collect();
+    assert!(occupancy.any_in(Bounds { min_x: 0, max_x: 1, min_y: 0, max_y: 1 }));
+    assert!(!occupancy.any_in(Bounds { min_x: 1, max_x: 33, min_y: -1, max_y: 1 }));
+    assert!(occupancy.any_in(Bounds { min_x: 1, max_x: 34, min_y: -1, max_y: 1 }));
+    assert!(occupancy.any_in(Bounds { min_x: -100, max_x: 100, min_y: 60, max_y: 65 }));
+    assert!(!occupancy.any_in(Bounds { min_x: -40, max_x: -40, min_y: 0, max_y: 100 }));
+}
+
+#[test]
+fn moves_and_merges() {
+    let a: Occupancy = rect_tiles(rects()[2]).collect();
+    let b: Occupancy = rect_tiles(rects()[1]).collect();
+    for (x, y) in [(32, -64), (5, -7)] {
+        let moved: HashSet<_> = a.move_rel(x, y).tiles().collect();
+        assert_eq!(moved, a.tiles().map(|(tx, ty)| (tx + x, ty + y)).collect());
+    }
+
+    let mut merged = a.clone();
+    merged.extend(&b);
+    merged.extend(&b);
+    assert_eq!(merged.len(), a.len() + b.len());
+    assert_eq!(merged, a.tiles().chain(b.tiles()).collect());
+}
@@ -0,0 +1,60 @@
+use std::collections::HashSet;
+
+use rust::building::Building;
+use rust::entity::{Entity, TilePosition};
+use rust::grid::Grid;
+use rust::position::Position;
+use rust::recipes::Recipe;
+use rust::rotation::Rotation;
+
+// An assembler with an inserter taking gears off a belt running east above it.
+fn row(input: Position) -> Grid {
+    let mut grid = Grid::new(Recipe::by_name("iron-gear-wheel").unwrap());
+    let mut assembler = Entity::from(Building::Assembler(String::from("iron-gear-wheel")));
+    assembler.position = TilePosition { x: 1, y: 2 };
+    grid.add_entity(assembler);
+    grid.place_belts(0, 0, Rotation::East, 4);
+    grid.place_inserters(4, 1, Rotation::South, 1, 1);
+    grid.set_output(Position::new(3, 0, Rotation::East));
+    grid.add_input(input);
+    grid
+}
+
+#[test]
+fn children_go_next_to_the_input_they_feed() {
+    for input in [Position::new(-1, 0, Rotation::East), Position::new(1, 6, Rotation::North)] {
+        let mut parent = row(input);
+        parent.add(row(Position::new(0, 0, Rotation::East)), 0, 0.0).unwrap();
+        parent.route_nets().unwrap();
+
+        let entities = parent.collect_entities();
+        let mut tiles = HashSet::new();
+        for entity in &entities {
+            assert!(entity.bounds().tiles().all(|tile| tiles.insert(tile)), "{} overlaps another entity", entity.name);
+        }
+
+        // Both rows have four belts, the child is placed so that only a few more connect its output to the input.
+        let belts = entities.iter().filter(|e| e.building == Building::Belt).count();
+        assert!(belts <= 4 + 4 + 4, "{} belts for input {:?}", belts, input);
+    }
+}
+
+#[test]
+fn rows_beyond_one_merger_are_merged_in_steps() {
+    let mut rows = row(Position::new(0, 0, Rotation::East));
+    for _ in 1..5 {
+        rows.add_parallel(row(Position::new(0, 0, Rotation::East))).unwrap();
+    }
+    // Balancers merge at most four belts, so four rows go through a 4-1 whose belt is merged with the fifth by a 2-1.
+    let mut parent = row(Position::new(-1, 0, Rotation::East));
+    parent.add(rows, 0, 0.0).unwrap();
+    parent.route_nets().unwrap();
+
+    let entities = parent.collect_entities();
+    let splitters = entities.iter().filter(|e| e.building == Building::Splitter).count();
+    assert_eq!(splitters, 3 + 1);
+    let mut tiles = HashSet::new();
+    for entity in &entities {
+        assert!(entity.bounds().tiles().all(|tile| tiles.insert(tile)), "{} overlaps another entity", entity.name);
+    }
+}
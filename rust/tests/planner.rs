@@ -0,0 +1,19 @@
+use rust::machines::MachineConfig;
+use rust::planner::{self, Plan};
+use rust::recipes::Recipe;
+use rust::synthesize;
+
+#[test]
+fn counts_round_up_without_float_noise() {
+    assert_eq!(planner::count(0.0), 0);
+    assert_eq!(planner::count(1e-9), 1);
+    assert_eq!(planner::count(2.0000000001), 2);
+    assert_eq!(planner::count(2.1), 3);
+}
+
+#[test]
+fn tiny_rates_still_get_a_machine() {
+    let plan = Plan::new(Recipe::producer("fast-inserter").unwrap(), 1e-9, &MachineConfig::default()).unwrap();
+    assert!(plan.steps().iter().all(|s| s.machine_count == 1), "a step without machines");
+    synthesize::synth(&plan).unwrap();
+}
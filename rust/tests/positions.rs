@@ -0,0 +1,77 @@
+use rust::building::Building;
+use rust::entity::{Blueprint, Entity, EntityPosition, TilePosition};
+use rust::rotation::Rotation;
+
+// Exported from the game, the example in main.py: two assemblers with an inserter between them.
+const ASSEMBLERS: &str = "0eNqVkdsKwjAMht8l1524g27rq4jIVsMMrOloO3GMvbvdFFE8gHdJaL7/Ix2hbnvsLLEHOQIpww7kbgRHDVftPPNDhyCBPGoQwJWeu8o51HVL3ES6UidijGKYBBAf8QIynvYCkD15whtvaYYD97pGGx78JgnojAvLhmeDAIyybLURMIQqLVabkGRR0SKGLSpvSUXaeGODxFtY8ggjdmh9mH0ISF4CPmDSv53X35zvquFIy1nl0y8IOKN1CyUp4iwvk3wbl2WZFtN0BaWoi9U=";
+
+// Splitters facing north and east, a big pole and a refinery, positioned like the game exports them.
+const EVEN_SIZES: &str = r#"{"blueprint": {"entities": [
+    {"entity_number": 1, "name": "splitter", "position": {"x": 1, "y": 3.5}},
+    {"entity_number": 2, "name": "splitter", "position": {"x": 2.5, "y": 1}, "direction": 2},
+    {"entity_number": 3, "name": "big-electric-pole", "position": {"x": 5, "y": 5}},
+    {"entity_number": 4, "name": "oil-refinery", "position": {"x": 10.5, "y": 10.5}, "recipe": "basic-oil-processing"}
+], "item": "blueprint", "version": 281479276199938}}"#;
+
+fn tiles(entity: &Entity) -> (isize, isize, isize, isize) {
+    let b = entity.bounds();
+    (b.min_x, b.max_x, b.min_y, b.max_y)
+}
+
+fn positions(blueprint: &Blueprint) -> Vec<(f64, f64)> {
+    blueprint.entities.iter().map(|e| e.entity_position()).map(|p| (p.x, p.y)).collect()
+}
+
+#[test]
+fn odd_sizes_sit_on_tile_centres() {
+    let blueprint = Blueprint::decode(ASSEMBLERS).unwrap();
+
+    assert_eq!(tiles(&blueprint.entities[0]), (-46, -43, -40, -37));
+    assert_eq!(tiles(&blueprint.entities[1]), (-43, -42, -39, -38));
+    assert_eq!(tiles(&blueprint.entities[2]), (-42, -39, -40, -37));
+    assert_eq!(positions(&blueprint), vec![(-44.5, -38.5), (-42.5, -38.5), (-40.5, -38.5)]);
+}
+
+#[test]
+fn even_sizes_sit_on_tile_edges() {
+    let blueprint = Blueprint::from_json(EVEN_SIZES).unwrap();
+
+    assert_eq!(tiles(&blueprint.entities[0]), (0, 2, 3, 4));
+    assert_eq!(tiles(&blueprint.entities[1]), (2, 3, 0, 2));
+    assert_eq!(tiles(&blueprint.entities[2]), (4, 6, 4, 6));
+    assert_eq!(tiles(&blueprint.entities[3]), (8, 13, 8, 13));
+}
+
+#[test]
+fn round_trip_keeps_positions() {
+    for blueprint in [Blueprint::decode(ASSEMBLERS).unwrap(), Blueprint::from_json(EVEN_SIZES).unwrap()] {
+        let decoded = Blueprint::decode(&blueprint.serialize()).unwrap();
+        assert_eq!(positions(&decoded), positions(&blueprint));
+        assert_eq!(decoded, blueprint);
+    }
+}
+
+#[test]
+fn written_at_the_centre_of_their_tiles() {
+    let mut belt = Entity::from(Building::Belt);
+    belt.position = TilePosition { x: 3, y: 4 };
+    assert_eq!(belt.entity_position(), EntityPosition { x: 3.5, y: 4.5 });
+
+    let mut assembler = Entity::from(Building::Assembler(String::from("iron-gear-wheel")));
+    assembler.position = TilePosition { x: 3, y: 4 };
+    assert_eq!(tiles(&assembler), (2, 5, 3, 6));
+    assert_eq!(assembler.entity_position(), EntityPosition { x: 3.5, y: 4.5 });
+
+    let mut splitter = Entity::from(Building::Splitter);
+    splitter.position = TilePosition { x: 3, y: 4 };
+    splitter.direction = Rotation::East.assemble();
+    assert_eq!(tiles(&splitter), (3, 4, 3, 5));
+    assert_eq!(splitter.entity_position(), EntityPosition { x: 3.5, y: 4.0 });
+}
+
+#[test]
+fn off_grid_positions_snap_to_the_nearest_tile() {
+    let position = EntityPosition { x: 3.4, y: -0.45 };
+    assert_eq!(position.to_tile((1, 1), (0, 0)), TilePosition { x: 3, y: -1 });
+    assert_eq!(position.to_tile((3, 3), (1, 1)), TilePosition { x: 3, y: -1 });
+}
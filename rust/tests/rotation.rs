@@ -0,0 +1,120 @@
+use std::collections::HashSet;
+
+use rust::bounds::Bounds;
+use rust::building::Building;
+use rust::entity::{Entity, TilePosition};
+use rust::grid::Grid;
+use rust::position::Position;
+use rust::recipes::Recipe;
+use rust::rotation::Rotation;
+
+fn tiles(b: Bounds) -> HashSet<(isize, isize)> {
+    (b.min_x..b.max_x).flat_map(|x| (b.min_y..b.max_y).map(move |y| (x, y))).collect()
+}
+
+#[test]
+fn compose_and_inverse() {
+    for a in Rotation::ALL {
+        assert_eq!(a.compose(a.inverse()), Rotation::North);
+        assert_eq!(a.mirror().mirror(), a);
+        assert_eq!(Rotation::from_direction(a.assemble()), a);
+        for b in Rotation::ALL {
+            assert_eq!(a.compose(b), b.compose(a));
+            assert_eq!(a.compose(b).compose(b.inverse()), a);
+        }
+    }
+    assert_eq!(Rotation::East.compose(Rotation::SouthEast), Rotation::SouthWest);
+    assert_eq!(Rotation::North.left(), Rotation::West);
+    assert_eq!(Rotation::NorthEast.cardinal(), Rotation::North);
+    assert_eq!(Rotation::East.mirror(), Rotation::West);
+}
+
+#[test]
+fn positions_turn_around_a_pivot() {
+    let p = Position::new(5, 3, Rotation::North);
+    assert_eq!(p.rotate_around((2, 2), Rotation::East), Position::new(1, 5, Rotation::East));
+    assert_eq!(p.rotate_around((2, 2), Rotation::South), Position::new(-1, 1, Rotation::South));
+    assert_eq!(p.mirror_around(2), Position::new(-1, 3, Rotation::North));
+    assert_eq!(Position::new(1, 1, Rotation::East).mirror_around(0).rotation, Rotation::West);
+
+    let mut q = p;
+    for _ in 0..4 {
+        q = q.rotate_around((7, -1), Rotation::East);
+    }
+    assert_eq!(q, p);
+}
+
+#[test]
+fn bounds_follow_their_tiles() {
+    let b = Bounds { min_x: -2, max_x: 3, min_y: 1, max_y: 3 };
+    for rotation in Rotation::CARDINAL {
+        let turned: HashSet<(isize, isize)> = tiles(b).into_iter().map(|(x, y)| {
+            let p = Position::north(x, y).rotate_around((1, -4), rotation);
+            (p.x, p.y)
+        }).collect();
+        assert_eq!(tiles(b.rotate_around((1, -4), rotation)), turned);
+    }
+    let mirrored: HashSet<(isize, isize)> = tiles(b).into_iter().map(|(x, y)| (2 * 4 - x, y)).collect();
+    assert_eq!(tiles(b.mirror_around(4)), mirrored);
+}
+
+#[test]
+fn entities_keep_their_footprint() {
+    let mut splitter = Entity::from(Building::Splitter);
+    splitter.position = TilePosition { x: 1, y: 0 };
+    splitter.rotate_around((0, 0), Rotation::East);
+    assert_eq!(Rotation::from_direction(splitter.direction), Rotation::East);
+    assert_eq!(tiles(splitter.bounds()), HashSet::from([(0, 0), (0, 1)]));
+
+    let mut assembler = Entity::from(Building::Assembler(String::from("iron-gear-wheel")));
+    assembler.position = TilePosition { x: 1, y: 1 };
+    assembler.rotate_around((0, 0), Rotation::South);
+    assert_eq!(tiles(assembler.bounds()), tiles(Bounds { min_x: -2, max_x: 1, min_y: -2, max_y: 1 }));
+}
+
+fn small_grid() -> Grid {
+    let mut grid = Grid::new(Recipe::by_name("iron-gear-wheel").unwrap());
+    let mut assembler = Entity::from(Building::Assembler(String::from("iron-gear-wheel")));
+    assembler.position = TilePosition { x: 1, y: 2 };
+    grid.add_entity(assembler);
+    grid.place_belts(0, 0, Rotation::East, 4);
+    grid.place_inserters(4, 1, Rotation::South, 1, 1);
+    grid.set_output(Position::new(3, 0, Rotation::East));
+    grid
+}
+
+fn footprints(grid: &Grid) -> Vec<(String, usize, isize, isize, isize, isize)> {
+    let mut result: Vec<_> = grid.collect_entities().iter().map(|e| {
+        let b = e.bounds();
+        (e.name.clone(), e.direction, b.min_x, b.max_x, b.min_y, b.max_y)
+    }).collect();
+    result.sort();
+    result
+}
+
+#[test]
+fn four_quarter_turns_give_the_same_grid() {
+    let original = small_grid();
+    let mut grid = original.clone();
+    for _ in 0..4 {
+        grid.rotate(Rotation::East);
+    }
+    assert_eq!(footprints(&grid), footprints(&original));
+    assert_eq!(grid.occupied(), original.occupied());
+    assert_eq!(grid.output(), original.output());
+
+    grid.mirror();
+    grid.mirror();
+    assert_eq!(footprints(&grid), footprints(&original));
+}
+
+#[test]
+fn turned_grids_keep_their_output_on_the_belt() {
+    for grid in small_grid().orientations() {
+        let output = grid.output().unwrap();
+        let belt = grid.collect_entities().into_iter().find(|e| e.position.x == output.x && e.position.y == output.y).unwrap();
+        assert_eq!(Rotation::from_direction(belt.direction), output.rotation);
+        assert_eq!(grid.occupied().len(), 9 + 4 + 1);
+    }
+    assert_eq!(small_grid().orientations().len(), 8);
+}
@@ -0,0 +1,34 @@
+use std::collections::{HashMap, HashSet};
+
+use rust::astar::Astar;
+use rust::bounds::Bounds;
+use rust::occupancy::Occupancy;
+use rust::position::Position;
+use rust::rotation::Rotation;
+use rust::router::path_tiles;
+
+#[test]
+fn penalties_count_on_the_way_into_the_end() {
+    let blocked = Occupancy::new();
+    let (begin, end) = (Position::new(0, 0, Rotation::East), Position::new(4, 0, Rotation::East));
+    let mut astar = Astar::new(begin, end, &blocked);
+    astar.set_underground(4);
+    // An underground from (1, 0) straight into the end would start on the expensive tile.
+    astar.set_penalties(HashMap::from([(Position::north(2, 0), 1000)]));
+    let path = astar.astar_belt().unwrap();
+    assert!(!path_tiles(begin, &path).contains(&Position::north(2, 0)), "{:?}", path);
+}
+
+#[test]
+fn paths_turning_back_keep_off_their_first_tile() {
+    // A wall right above the start, which undergrounds can pass but nothing can stand on.
+    let mut blocked = Occupancy::new();
+    blocked.insert_rect(Bounds { min_x: -5, max_x: 6, min_y: -1, max_y: 0 });
+    let (begin, end) = (Position::new(0, 0, Rotation::East), Position::new(1, -3, Rotation::North));
+    let mut astar = Astar::new(begin, end, &blocked);
+    astar.set_underground(4);
+    // Looping back to go under the wall right from the first tile would put an entrance on top of its belt.
+    let path = astar.astar_belt().unwrap();
+    let tiles = path_tiles(begin, &path);
+    assert_eq!(tiles.iter().collect::<HashSet<_>>().len(), tiles.len(), "{:?}", path);
+}
@@ -0,0 +1,52 @@
+use std::collections::HashMap;
+
+use rust::building::Building;
+use rust::machines::MachineConfig;
+use rust::planner::Plan;
+use rust::recipes::Recipe;
+use rust::synthesize;
+
+fn plan(item: &str, rate: f64) -> Plan {
+    Plan::new(Recipe::producer(item).unwrap(), rate, &MachineConfig::default()).unwrap()
+}
+
+#[test]
+fn shared_steps_are_built_once() {
+    // Iron plates go into the motors and into the gears they are made of.
+    let plan = plan("electric-motor", 1.0);
+    assert!(plan.steps().iter().any(|s| s.consumers.len() > 1 && !s.inputs.is_empty()));
+
+    let grid = synthesize::synth(&plan).unwrap();
+    let mut built: HashMap<Building, usize> = HashMap::new();
+    for entity in grid.collect_entities() {
+        *built.entry(entity.building).or_default() += 1;
+    }
+    let mut planned: HashMap<Building, usize> = HashMap::new();
+    for step in plan.steps().iter().filter(|s| !s.inputs.is_empty()) {
+        *planned.entry(step.recipe.building()).or_default() += step.machine_count;
+    }
+    // Rows whose inserters can not keep up get more machines, those of shared steps have to show up exactly once.
+    for step in plan.steps().iter().filter(|s| !s.inputs.is_empty()) {
+        let (building, count) = (step.recipe.building(), built.get(&step.recipe.building()).copied().unwrap_or(0));
+        if step.consumers.len() > 1 {
+            assert_eq!(count, planned[&building], "{}", step.recipe.name());
+        } else {
+            assert!(count >= planned[&building], "{}", step.recipe.name());
+        }
+    }
+    assert!(grid.demands().is_empty());
+    assert!(built.contains_key(&Building::Splitter), "the shared output is not split");
+}
+
+#[test]
+fn plans_and_layouts_are_deterministic() {
+    let names = |plan: &Plan| plan.steps().iter().map(|s| s.recipe.name().to_string()).collect::<Vec<_>>();
+    let (first, second) = (plan("fast-inserter", 1.0), plan("fast-inserter", 1.0));
+    assert_eq!(names(&first), names(&second));
+    for (a, b) in first.steps().iter().zip(second.steps()) {
+        assert_eq!(a.consumers, b.consumers);
+    }
+
+    let (first, second) = (plan("electric-motor", 1.0), plan("electric-motor", 1.0));
+    assert_eq!(synthesize::synth(&first).unwrap().to_blueprint(), synthesize::synth(&second).unwrap().to_blueprint());
+}
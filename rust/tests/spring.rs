@@ -0,0 +1,21 @@
+#![cfg(feature = "spring")]
+
+use rust::entity::Blueprint;
+use rust::machines::{self, MachineConfig, ModuleConfig};
+use rust::planner::Plan;
+use rust::recipes::Recipe;
+use rust::spring_system::SpringSystem;
+
+#[test]
+fn spring_layouts_use_the_configured_machines() {
+    let mut config = MachineConfig::default();
+    config.use_machine("assembling-machine-2").unwrap();
+    let speed = ModuleConfig { machine: vec![machines::module("speed-module").unwrap()], ..ModuleConfig::default() };
+    config.set_modules("iron-gear-wheel", speed).unwrap();
+    let plan = Plan::new(Recipe::by_name("iron-gear-wheel").unwrap(), 2.0, &config).unwrap();
+
+    let blueprint = Blueprint::decode(&SpringSystem::new(&plan, config, 0).unwrap().partial_blueprint()).unwrap();
+    let gears = blueprint.entities.iter().find(|e| e.name.starts_with("assembling-machine")).expect("no assembler for the gears");
+    assert_eq!(gears.name, "assembling-machine-2");
+    assert_eq!(gears.items.get("speed-module"), Some(&1));
+}